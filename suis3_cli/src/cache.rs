@@ -0,0 +1,166 @@
+// ~/.suis3/cache.json keeps the bucket and object names last listed on each network, so completion
+// does not ask the chain on every key. la, ls, ll and refresh write it; a list older than CACHE_TTL
+// is listed again. with --no-cache it is neither read nor written.
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+use crate::operations;
+use crate::settings;
+
+// milliseconds a cached list is used for completion
+pub const CACHE_TTL: u64 = 10 * 60 * 1000;
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Names {
+    // milliseconds since the epoch of the listing
+    pub ts: u64,
+    pub names: Vec<String>,
+}
+
+impl Names {
+    fn new(names: Vec<String>) -> Names {
+        Names { ts: now(), names }
+    }
+
+    fn is_fresh(&self) -> bool {
+        now().saturating_sub(self.ts) < CACHE_TTL
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct NetworkCache {
+    buckets: Option<Names>,
+    // the object keys of each bucket, with their leading slash
+    #[serde(default)]
+    objects: HashMap<String, Names>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Cache {
+    #[serde(default)]
+    networks: HashMap<String, NetworkCache>,
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp_millis() as u64
+}
+
+fn cache_path() -> Result<PathBuf, anyhow::Error> {
+    let home = dirs::home_dir().ok_or(anyhow!("Cannot find the home directory"))?;
+    Ok(home.join(".suis3").join("cache.json"))
+}
+
+// a missing or unreadable cache is an empty one
+fn load() -> Cache {
+    cache_path()
+        .ok()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+// the cache only saves listings, a failure to write it is logged and the command goes on
+fn update(change: impl FnOnce(&mut NetworkCache)) {
+    if settings::get().no_cache {
+        return;
+    }
+    let mut cache = load();
+    change(cache.networks.entry(settings::get().config.network).or_default());
+    let saved = cache_path().and_then(|path| {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string(&cache)?)?;
+        Ok(())
+    });
+    if let Err(e) = saved {
+        log::debug!("Cannot write the completion cache: {}", e);
+    }
+}
+
+pub fn save_buckets(names: Vec<String>) {
+    update(|cache| {
+        // a bucket gone since is forgotten with its objects
+        cache.objects.retain(|bucket, _| names.contains(bucket));
+        cache.buckets = Some(Names::new(names));
+    });
+}
+
+pub fn save_objects(bucket_name: &str, keys: Vec<String>) {
+    update(|cache| {
+        cache.objects.insert(bucket_name.to_owned(), Names::new(keys));
+    });
+}
+
+fn fresh(pick: impl FnOnce(&NetworkCache) -> Option<&Names>) -> Option<Vec<String>> {
+    if settings::get().no_cache {
+        return None;
+    }
+    let cache = load();
+    let names = pick(cache.networks.get(&settings::get().config.network)?)?;
+    names.is_fresh().then(|| names.names.clone())
+}
+
+// the bucket names, from the cache unless it is stale
+pub async fn bucket_names() -> Result<Vec<String>, anyhow::Error> {
+    if let Some(names) = fresh(|cache| cache.buckets.as_ref()) {
+        return Ok(names);
+    }
+    refresh_buckets().await
+}
+
+// the object keys of the bucket, from the cache unless it is stale
+pub async fn object_keys(bucket_name: &str) -> Result<Vec<String>, anyhow::Error> {
+    if let Some(keys) = fresh(|cache| cache.objects.get(bucket_name)) {
+        return Ok(keys);
+    }
+    refresh_objects(bucket_name).await
+}
+
+pub async fn refresh_buckets() -> Result<Vec<String>, anyhow::Error> {
+    let names = operations::list_buckets().await?.into_iter().map(|b| b.name).collect::<Vec<_>>();
+    save_buckets(names.clone());
+    Ok(names)
+}
+
+pub async fn refresh_objects(bucket_name: &str) -> Result<Vec<String>, anyhow::Error> {
+    let list = operations::get_bucket_objects(bucket_name.to_owned()).await?;
+    let keys = list.objects.into_iter().map(|obj| obj.uri).collect::<Vec<_>>();
+    save_objects(bucket_name, keys.clone());
+    Ok(keys)
+}
+
+// the uris completing a partial one: the buckets while the bucket is typed, then its objects.
+// the word is matched as typed, scheme and case included
+pub async fn complete(word: &str) -> Result<Vec<String>, anyhow::Error> {
+    let (scheme, rest) = match word.find("://") {
+        Some(i) => word.split_at(i + 3),
+        None => ("suis3://", word),
+    };
+    match rest.split_once('/') {
+        None => {
+            let names = bucket_names().await?;
+            Ok(candidates(&names, rest, scheme, "/"))
+        }
+        Some((bucket, key)) => {
+            let keys = object_keys(&bucket.to_lowercase()).await?;
+            let prefix = "/".to_owned() + key;
+            Ok(candidates(&keys, &prefix, &format!("{}{}", scheme, bucket), ""))
+        }
+    }
+}
+
+// the names starting with the prefix, between the head and the tail, sorted
+pub fn candidates(names: &[String], prefix: &str, head: &str, tail: &str) -> Vec<String> {
+    let mut found = names
+        .iter()
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| format!("{}{}{}", head, name, tail))
+        .collect::<Vec<_>>();
+    found.sort();
+    found
+}
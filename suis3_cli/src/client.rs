@@ -0,0 +1,275 @@
+// the bucket and object operations of suis3 for other programs, the cli is built on the same functions.
+// a client is configured with `SuiS3Client::builder()`, values left out take the defaults of the cli,
+// not the config file or SUIS3_* variables. each client keeps its own configuration, the operations it runs
+// read it through settings::get(), walrus::backend() and utils::sui_client() instead of the process wide ones.
+// object names may be given with or without the leading slash the contract stores.
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use sui_keys::keystore::FileBasedKeystore;
+use sui_sdk::types::base_types::{ObjectID, SuiAddress};
+use sui_sdk::types::digests::TransactionDigest;
+use sui_sdk::SuiClient;
+
+use crate::operations::{self, BucketHistory, BucketInfo, BucketObjectsList, BudgetCache, ObjectCache};
+use crate::settings::{Config, ConfigValues, Settings, CONFIG_KEYS};
+use crate::utils;
+use crate::walrus::{self, BlobMeta, WalrusBackend};
+
+#[derive(Clone)]
+pub struct SuiS3Client {
+    context: Arc<Context>,
+}
+
+// what the operations of a client run with. sui and keystore are left out while the client is built
+#[derive(Clone)]
+pub(crate) struct Context {
+    pub(crate) settings: Settings,
+    pub(crate) sui: Option<SuiClient>,
+    pub(crate) keystore: Option<Arc<FileBasedKeystore>>,
+    pub(crate) walrus_backend: Option<Arc<dyn WalrusBackend>>,
+    pub(crate) objects: ObjectCache,
+    pub(crate) budgets: BudgetCache,
+}
+
+tokio::task_local! {
+    static CONTEXT: Arc<Context>;
+}
+
+// the context of the client whose operation is running, none in the cli
+pub(crate) fn context() -> Option<Arc<Context>> {
+    CONTEXT.try_with(|context| context.clone()).ok()
+}
+
+#[derive(Default)]
+pub struct SuiS3ClientBuilder {
+    values: ConfigValues,
+    wallet_dir: Option<PathBuf>,
+    active_address: Option<SuiAddress>,
+    read_only_wallet: bool,
+    gas_coin: Option<ObjectID>,
+    walrus_backend: Option<Arc<dyn WalrusBackend>>,
+    read_from_aggregator: bool,
+}
+
+impl SuiS3ClientBuilder {
+    pub fn network(mut self, network: &str) -> Self {
+        self.values.network = Some(network.to_owned());
+        self
+    }
+
+    // the fullnode of the network without it
+    pub fn rpc_url(mut self, rpc_url: &str) -> Self {
+        self.values.rpc_url = Some(rpc_url.to_owned());
+        self
+    }
+
+    pub fn package_id(mut self, package_id: &str) -> Self {
+        self.values.package_id = Some(package_id.to_owned());
+        self
+    }
+
+    pub fn buckets_root(mut self, buckets_root: &str) -> Self {
+        self.values.buckets_root = Some(buckets_root.to_owned());
+        self
+    }
+
+    pub fn gas_budget(mut self, gas_budget: u64) -> Self {
+        self.values.gas_budget = Some(gas_budget);
+        self
+    }
+
+    // the wallet with the keystore and client.yaml, created when missing unless read_only_wallet
+    pub fn wallet_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.wallet_dir = Some(dir.into());
+        self
+    }
+
+    // the signing address, one of the keystore, instead of its first address. client.yaml is left as is
+    pub fn active_address(mut self, address: SuiAddress) -> Self {
+        self.active_address = Some(address);
+        self
+    }
+
+    // fail rather than create a missing client config, keystore or key, or set the active address
+    pub fn read_only_wallet(mut self) -> Self {
+        self.read_only_wallet = true;
+        self
+    }
+
+    // the SUI coin paying the gas, instead of the first one of the address holding enough
+    pub fn gas_coin(mut self, coin_id: ObjectID) -> Self {
+        self.gas_coin = Some(coin_id);
+        self
+    }
+
+    // the walrus cli named by walrus_bin without it
+    pub fn walrus_backend(mut self, backend: Arc<dyn WalrusBackend>) -> Self {
+        self.walrus_backend = Some(backend);
+        self
+    }
+
+    pub fn walrus_bin(mut self, walrus_bin: &str) -> Self {
+        self.values.walrus_bin = Some(walrus_bin.to_owned());
+        self
+    }
+
+    pub fn aggregator_url(mut self, aggregator_url: &str) -> Self {
+        self.values.aggregator_url = Some(aggregator_url.to_owned());
+        self
+    }
+
+    // download_object reads the blobs over http from aggregator_url, with the walrus cli only when it fails,
+    // like --endpoint-walrus-aggregator
+    pub fn read_from_aggregator(mut self) -> Self {
+        self.read_from_aggregator = true;
+        self
+    }
+
+    pub async fn build(self) -> Result<SuiS3Client, anyhow::Error> {
+        // the same checks as `config set`
+        for key in CONFIG_KEYS {
+            if let Some(value) = self.values.get(key) {
+                ConfigValues::default().set(key, Some(&value)).map_err(|e| anyhow!("{}: {}", key, e))?;
+            }
+        }
+        let config = Config::resolve(&self.values, &ConfigValues::default(), &ConfigValues::default());
+        let mut context = Context {
+            settings: Settings {
+                flags: self.values,
+                wallet_dir: self.wallet_dir,
+                active_address: self.active_address,
+                read_only_wallet: self.read_only_wallet,
+                gas_coin: self.gas_coin,
+                read_aggregator: self.read_from_aggregator.then(|| config.aggregator_url.clone()),
+                config,
+                ..Default::default()
+            },
+            sui: None,
+            keystore: None,
+            walrus_backend: self.walrus_backend,
+            objects: Default::default(),
+            budgets: Default::default(),
+        };
+
+        // the rpc client and the keystore are looked up with the settings of this client
+        let partial = Arc::new(Context { walrus_backend: None, ..context.clone() });
+        let (sui, keystore) = CONTEXT
+            .scope(partial, async {
+                let sui = utils::sui_client().await?;
+                utils::retrieve_wallet()?;
+                Ok::<_, anyhow::Error>((sui, utils::keystore()?))
+            })
+            .await?;
+        context.sui = Some(sui);
+        context.keystore = Some(keystore);
+        Ok(SuiS3Client { context: Arc::new(context) })
+    }
+}
+
+// bucket names are looked up in lower case, as the cli parses them from a uri
+fn bucket(name: &str) -> String {
+    name.to_lowercase()
+}
+
+fn key(obj_name: &str) -> String {
+    "/".to_owned() + obj_name.trim_start_matches('/')
+}
+
+impl SuiS3Client {
+    pub fn builder() -> SuiS3ClientBuilder {
+        SuiS3ClientBuilder::default()
+    }
+
+    // the defaults of the cli: testnet, the published package and buckets root, the sui config dir wallet
+    pub async fn new() -> Result<SuiS3Client, anyhow::Error> {
+        Self::builder().build().await
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.context.settings.config
+    }
+
+    pub fn sui(&self) -> &SuiClient {
+        self.context.sui.as_ref().unwrap()
+    }
+
+    pub fn keystore(&self) -> &FileBasedKeystore {
+        self.context.keystore.as_ref().unwrap()
+    }
+
+    // run the operation with the configuration of this client. like a command of the cli, each call
+    // starts without cached object metadata or gas estimates, so it sees the writes made since
+    async fn run<F: Future>(&self, operation: F) -> F::Output {
+        let context = Context { objects: Default::default(), budgets: Default::default(), ..(*self.context).clone() };
+        CONTEXT.scope(Arc::new(context), operation).await
+    }
+
+    pub async fn create_bucket(&self, name: &str, tags: Vec<String>) -> Result<TransactionDigest, anyhow::Error> {
+        self.run(operations::create_bucket(bucket(name), tags)).await
+    }
+
+    pub async fn list_buckets(&self) -> Result<Vec<BucketInfo>, anyhow::Error> {
+        self.run(operations::list_buckets()).await
+    }
+
+    pub async fn bucket_exists(&self, name: &str) -> Result<bool, anyhow::Error> {
+        self.run(operations::bucket_exists(&bucket(name))).await
+    }
+
+    pub async fn delete_bucket(&self, name: &str) -> Result<TransactionDigest, anyhow::Error> {
+        self.run(operations::delete_bucket(bucket(name))).await
+    }
+
+    pub async fn tag_bucket(&self, name: &str, tags: Vec<String>) -> Result<TransactionDigest, anyhow::Error> {
+        self.run(operations::tag_bucket(bucket(name), tags)).await
+    }
+
+    pub async fn list_bucket_tags(&self, name: &str) -> Result<Vec<String>, anyhow::Error> {
+        self.run(operations::list_bucket_tags(bucket(name))).await
+    }
+
+    pub async fn list_objects(&self, bucket_name: &str) -> Result<BucketObjectsList, anyhow::Error> {
+        self.run(operations::get_bucket_objects(bucket(bucket_name))).await
+    }
+
+    // the last `limit` writes on the bucket among the newest `scan` transactions of the package, see HISTORY_SCAN
+    pub async fn history(&self, bucket_name: &str, limit: usize, scan: usize) -> Result<BucketHistory, anyhow::Error> {
+        self.run(operations::get_bucket_history(bucket(bucket_name), limit, scan)).await
+    }
+
+    // upload the file to walrus, then save its metadata as the object
+    pub async fn put_object(&self, bucket_name: &str, obj_name: &str, file: &str, tags: Vec<String>) -> Result<(BlobMeta, TransactionDigest), anyhow::Error> {
+        self.run(operations::put_object(&bucket(bucket_name), &key(obj_name), &file.to_owned(), tags)).await
+    }
+
+    pub async fn get_object(&self, bucket_name: &str, obj_name: &str) -> Result<BlobMeta, anyhow::Error> {
+        self.run(operations::get_object(bucket(bucket_name), key(obj_name))).await
+    }
+
+    // the walrus blob id of the object
+    pub async fn get_object_id(&self, bucket_name: &str, obj_name: &str) -> Result<String, anyhow::Error> {
+        self.run(operations::get_object_id(bucket(bucket_name), key(obj_name))).await
+    }
+
+    // read the object from walrus into the file
+    pub async fn download_object(&self, bucket_name: &str, obj_name: &str, dest_file: &str) -> Result<BlobMeta, anyhow::Error> {
+        let meta = self.get_object(bucket_name, obj_name).await?;
+        self.run(async { walrus::backend().download(&meta.walrus_blob_id, &dest_file.to_owned()) }).await?;
+        Ok(meta)
+    }
+
+    pub async fn delete_object(&self, bucket_name: &str, obj_name: &str) -> Result<TransactionDigest, anyhow::Error> {
+        self.run(operations::delete_object(bucket(bucket_name), key(obj_name))).await
+    }
+
+    pub async fn tag_object(&self, bucket_name: &str, obj_name: &str, tags: Vec<String>) -> Result<TransactionDigest, anyhow::Error> {
+        self.run(operations::tag_object(bucket(bucket_name), key(obj_name), tags)).await
+    }
+
+    pub async fn list_object_tags(&self, bucket_name: &str, obj_name: &str) -> Result<Vec<String>, anyhow::Error> {
+        self.run(operations::list_object_tags(bucket(bucket_name), key(obj_name))).await
+    }
+}
@@ -1,186 +1,2533 @@
-use regex::Regex;
 #[cfg(feature = "async")]
 
 use clap::{Parser, ValueEnum};
-use chrono::prelude::*;
+use futures::stream::{self, StreamExt};
+use std::collections::HashMap;
 use std::path::Path;
 use std::env;
 use std::fs;
+use std::io::{BufRead, BufReader, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::time::Instant;
 
+use sui_json_rpc_types::SuiObjectDataOptions;
+use sui_sdk::types::base_types::{ObjectID, SuiAddress};
+use sui_sdk::types::digests::TransactionDigest;
+
+use super::cache;
 use super::operations;
+use super::porcelain;
+use super::render;
+#[cfg(feature = "serve")]
+use super::serve;
+use super::settings;
+use super::utils;
+pub use super::utils::{parse_uri, SuiS3Uri};
 use super::walrus;
 
-static SUIS3_REGEXP: &str = r#"[sS][uU][iI][sS]3:\/\/(?P<bucket>[A-Za-z0-9\-\._]+)(?P<object>[A-Za-z0-9\-\._\/]*)"#;
+// write a line of command output, a closed pipe (e.g. `| head`) is not an error of the command
+macro_rules! outln {
+    ($out:expr) => {{
+        let _ = writeln!($out);
+    }};
+    ($out:expr, $($arg:tt)*) => {{
+        let _ = writeln!($out, $($arg)*);
+    }};
+}
+
+// an error of the command, on stderr; the command then exits non-zero
+macro_rules! errln {
+    ($($arg:tt)*) => {{
+        print_error("Error", &format!($($arg)*));
+        fail_with(1);
+    }};
+}
+
+// an error value of the command, the exit code follows its kind
+macro_rules! failln {
+    ($e:expr) => {{
+        let e = &$e;
+        print_error(e.error_kind(), &e.to_string());
+        fail_with(e.exit_code());
+    }};
+}
+
+// a progress or confirmation line of the command, left out with --quiet
+macro_rules! statusln {
+    ($out:expr, $($arg:tt)*) => {{
+        if !settings::get().quiet {
+            let _ = writeln!($out, $($arg)*);
+        }
+    }};
+}
+
+// characters of a blob id shown without --full-blob-id
+const BLOB_ID_PREFIX: usize = 12;
+
+// the exit code of the first error of the running command, 0 while none
+static EXIT_CODE: AtomicI32 = AtomicI32::new(0);
+
+fn fail_with(code: i32) {
+    let _ = EXIT_CODE.compare_exchange(0, code, Ordering::Relaxed, Ordering::Relaxed);
+}
+
+// the running command prints --json, its errors are json records too
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+// {"error":{"kind":...,"message":...}} under --json. stdout is flushed first, a record never lands inside the output
+fn print_error(kind: &str, message: &str) {
+    if JSON_ERRORS.load(Ordering::Relaxed) {
+        let _ = std::io::stdout().flush();
+        eprintln!("{}", serde_json::json!({ "error": { "kind": kind, "message": message } }));
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+trait ExitCode {
+    fn exit_code(&self) -> i32;
+    // the SuiS3Error variant, Error for any other failure
+    fn error_kind(&self) -> &'static str;
+}
+
+impl ExitCode for anyhow::Error {
+    fn exit_code(&self) -> i32 {
+        self.downcast_ref::<operations::SuiS3Error>().map_or(1, |e| e.exit_code())
+    }
+
+    fn error_kind(&self) -> &'static str {
+        self.downcast_ref::<operations::SuiS3Error>().map_or("Error", |e| e.kind())
+    }
+}
+
+impl ExitCode for std::io::Error {
+    fn exit_code(&self) -> i32 {
+        1
+    }
+
+    fn error_kind(&self) -> &'static str {
+        "Error"
+    }
+}
+
+// buckets read at the same time by la --stats
+const STATS_CONCURRENCY: usize = 8;
+
+// cat refuses objects larger than this unless --limit or --all
+const CAT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+// put of an http(s) url stops downloading past this
+const URL_PUT_MAX_SIZE: u64 = 1024 * 1024 * 1024;
+
+// the bucket name as typed in the uri when it is not a valid one, the uri regex does not match it.
+// paired with the name suggested instead
+fn invalid_bucket_name(uri: &str) -> Option<(String, Option<String>)> {
+    let (scheme, rest) = uri.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case("suis3") && !scheme.eq_ignore_ascii_case("s3") {
+        return None;
+    }
+    let typed = rest.split('/').next().unwrap_or("");
+    if utils::is_valid_bucket_name(typed) {
+        return None;
+    }
+    Some((typed.to_owned(), utils::sanitize_bucket_name(typed)))
+}
+
+fn bucket_name_error(typed: &str, suggestion: &Option<String>) -> String {
+    let hint = suggestion.as_ref().map(|s| format!(" Did you mean '{}'?", s)).unwrap_or_default();
+    format!("Bucket name '{}' is not valid, a name holds letters, digits, '-', '.' and '_'.{}", typed, hint)
+}
 
 #[derive(Parser, Debug)]
 #[command(name = "suis3")]
 pub struct Cli {
+    #[arg(
+        long,
+        global = true,
+        help = "submit write transactions without waiting for local execution, listings may lag behind"
+    )]
+    pub no_wait: bool,
+
+    #[arg(short, long, global = true, conflicts_with = "verbose", help = "print only the errors and the requested data, the exit code tells the success")]
+    pub quiet: bool,
+
+    #[arg(short, long, global = true, help = "also print the details of the transactions")]
+    pub verbose: bool,
+
+    #[arg(long, global = true, help = "also print the raw json of every event of the read transactions, with the --verbose output")]
+    pub dump_events: bool,
+
+    #[arg(long, global = true, help = "indent the --json output for reading, without it each record is one line for pipes")]
+    pub json_pretty: bool,
+
+    #[arg(long, global = true, help = "complete from the chain, neither reading nor writing the cache of bucket and object names")]
+    pub no_cache: bool,
+
+    #[arg(short, long, global = true, help = "answer yes to every confirmation, without it a prompt that cannot be asked fails the command")]
+    pub yes: bool,
+
+    #[arg(long, global = true, help = "skip the check of the rpc and walrus before put -r, get -r, rb -r, rename-bucket and reprefix")]
+    pub no_preflight: bool,
+
+    #[arg(long, global = true, value_parser = utils::parse_address, help = "sign with this address of the keystore for this session, without making it the active address of the wallet")]
+    pub assume_active_address: Option<SuiAddress>,
+
+    #[arg(long, global = true, help = "print the gas used and the SUI left on the signing address after each write transaction")]
+    pub show_balance: bool,
+
+    #[arg(long, global = true, help = "budget the gas of each write transaction by its dry run instead of the gas_budget config")]
+    pub budget_from_dryrun: bool,
+
+    #[arg(long, global = true, default_value_t = 20, requires = "budget_from_dryrun", help = "percent added to the dry run cost with --budget-from-dryrun")]
+    pub budget_margin: u64,
+
+    #[arg(long, global = true, value_parser = utils::parse_object_id, help = "pay the gas with this SUI coin of the signing address, it must hold the gas budget")]
+    pub gas_coin: Option<ObjectID>,
+
+    #[arg(long, global = true, help = "never create or change the client config and keystore, fail when one, a key or the active address is missing")]
+    pub read_only_wallet: bool,
+
+    #[arg(long, global = true, help = "wallet profile in ~/.suis3/profiles/<name>, with its own client config and keystore")]
+    pub profile: Option<String>,
+
+    #[arg(long, global = true, value_parser = settings::NETWORKS, help = "sui network for this session")]
+    pub network: Option<String>,
+
+    #[arg(long, global = true, help = "sui fullnode rpc url for this session")]
+    pub rpc_url: Option<String>,
+
+    #[arg(long, global = true, help = "suis3 package id for this session")]
+    pub package_id: Option<String>,
+
+    #[arg(long, global = true, help = "buckets root object id for this session")]
+    pub buckets_root: Option<String>,
+
+    #[arg(long, global = true, help = "gas budget (MIST) of each transaction for this session")]
+    pub gas_budget: Option<u64>,
+
+    #[arg(long, global = true, help = "path of the walrus binary for this session")]
+    pub walrus_bin: Option<String>,
+
+    #[arg(long, global = true, help = "seconds a walrus call may take before it is stopped, 300 by default")]
+    pub walrus_timeout: Option<u64>,
+
+    #[arg(long, global = true, help = "public walrus aggregator url of the links printed by share, for this session")]
+    pub aggregator_url: Option<String>,
+
+    #[arg(long, global = true, num_args = 0..=1, value_name = "URL",
+          help = "read the blobs of get and cat over http from this walrus aggregator, the aggregator_url without a value, falling back to the walrus cli")]
+    pub endpoint_walrus_aggregator: Option<Option<String>>,
+
+    #[arg(long, global = true, help = "folder of get without a destination for this session, the current folder by default")]
+    pub download_dir: Option<String>,
+
+    #[arg(long, global = true, help = "rb -r of more objects than this asks for a confirmation (or --yes) for this session, 10 by default")]
+    pub confirm_threshold: Option<u64>,
+
     #[command(subcommand)]
     pub suis3_cmd: Option<SuiS3Cmd>,
 }
 
+impl Cli {
+    pub fn config_flags(&self) -> settings::ConfigValues {
+        settings::ConfigValues {
+            network: self.network.clone(),
+            rpc_url: self.rpc_url.clone(),
+            package_id: self.package_id.clone(),
+            buckets_root: self.buckets_root.clone(),
+            gas_budget: self.gas_budget,
+            walrus_bin: self.walrus_bin.clone(),
+            walrus_timeout: self.walrus_timeout,
+            aggregator_url: self.aggregator_url.clone(),
+            download_dir: self.download_dir.clone(),
+            confirm_threshold: self.confirm_threshold,
+        }
+    }
+}
+
+// a line of the shell: one command, -y/--yes is a flag anywhere before `--` and answers the prompts of that line
+#[derive(Parser, Debug)]
+pub struct ShellLine {
+    #[arg(short, long, global = true, help = "answer yes to the confirmations of this line")]
+    pub yes: bool,
+
+    #[command(subcommand)]
+    pub suis3_cmd: SuiS3Cmd,
+}
+
 #[derive(Parser, PartialEq, Debug)]
 pub enum SuiS3Cmd {
-    #[command(name = "la", about = "list all buckets")]
-    ListAll,
+    #[command(name = "la", about = r#"list all buckets with their tags
+    la [--porcelain]
+also count the objects and their total size in each bucket
+    la --stats
+print only the number of buckets
+    la --count"#)]
+    ListAll {
+        #[arg(long, help = "print the stable tab-separated layout for scripts")]
+        porcelain: bool,
+        #[arg(long, help = "also show the number of objects and their total size, read from each bucket")]
+        stats: bool,
+        #[arg(long, conflicts_with_all = ["porcelain", "stats"], help = "print only the number of buckets")]
+        count: bool,
+    },
 
     #[command(
         name = "ls",
         about = r#"list all buckets, or list all objects of a bucket
-    ls s3://<bucket>"#
+    ls s3://<bucket>
+list one object
+    ls s3://<bucket>/<object>
+list a page of objects in key order
+    ls s3://<bucket> --limit <n> --start-after <object>
+list the objects with all the tags, or with any of them
+    ls s3://<bucket> --tag <key>=<value> --tag <key> [--any]
+list the objects written in a time range, rfc3339 or ago as 7d, 12h
+    ls s3://<bucket> --after <time> --before <time>
+print the stable tab-separated layout for scripts
+    ls [s3://<bucket>] --porcelain
+print only the uris of the buckets, or of the objects, one per line, e.g. for xargs
+    ls [s3://<bucket>] --keys-only
+print only the number of buckets, or of the objects listed with the same filters
+    ls [s3://<bucket>] [--tag <key>=<value>] ... --count"#
+    )]
+    List {
+        uri: Option<String>,
+        #[arg(long, help = "list at most <n> objects, in key order")]
+        limit: Option<usize>,
+        #[arg(long, help = "list the objects after this key, in key order")]
+        start_after: Option<String>,
+        #[arg(long = "tag", help = "list only the objects with this tag, key=value or key, repeatable")]
+        tags: Vec<String>,
+        #[arg(long, requires = "tags", help = "list the objects with any of the tags rather than all of them")]
+        any: bool,
+        #[arg(long, value_parser = utils::parse_time, help = "list the objects written at or after the time, rfc3339 or ago as 7d, 12h, 30m")]
+        after: Option<u64>,
+        #[arg(long, value_parser = utils::parse_time, help = "list the objects written before the time, rfc3339 or ago as 7d, 12h, 30m")]
+        before: Option<u64>,
+        #[arg(long, help = "print the stable tab-separated layout for scripts")]
+        porcelain: bool,
+        #[arg(long, conflicts_with = "porcelain", help = "print only the suis3:// uri of each bucket or object, no header nor time")]
+        keys_only: bool,
+        #[arg(long, conflicts_with_all = ["porcelain", "keys_only"], help = "print only the number of buckets, or of the objects of the bucket")]
+        count: bool,
+    },
+
+    #[command(
+        name = "ll",
+        about = r#"list all buckets with their tags, or list all objects detail of the bucket
+    ll s3://<bucket>
+show the detail of one object
+    ll s3://<bucket>/<object>
+list a page of objects detail in key order
+    ll s3://<bucket> --limit <n> --start-after <object>
+list the objects detail with all the tags, or with any of them
+    ll s3://<bucket> --tag <key>=<value> --tag <key> [--any]
+list the objects detail written in a time range, rfc3339 or ago as 7d, 12h
+    ll s3://<bucket> --after <time> --before <time>
+list the objects detail as JSON Lines, one object per line
+    ll s3://<bucket> --json
+print the stable tab-separated layout for scripts
+    ll [s3://<bucket>] --porcelain
+print only the number of buckets, or of the objects listed with the same filters
+    ll [s3://<bucket>] [--tag <key>=<value>] ... --count"#
+    )]
+    Detail {
+        uri: Option<String>,
+        #[arg(long, help = "list at most <n> objects, in key order")]
+        limit: Option<usize>,
+        #[arg(long, help = "list the objects after this key, in key order")]
+        start_after: Option<String>,
+        #[arg(long = "tag", help = "list only the objects with this tag, key=value or key, repeatable")]
+        tags: Vec<String>,
+        #[arg(long, requires = "tags", help = "list the objects with any of the tags rather than all of them")]
+        any: bool,
+        #[arg(long, value_parser = utils::parse_time, help = "list the objects written at or after the time, rfc3339 or ago as 7d, 12h, 30m")]
+        after: Option<u64>,
+        #[arg(long, value_parser = utils::parse_time, help = "list the objects written before the time, rfc3339 or ago as 7d, 12h, 30m")]
+        before: Option<u64>,
+        #[arg(long, conflicts_with = "porcelain", help = "print one JSON object per line instead of the table")]
+        json: bool,
+        #[arg(long, help = "print the stable tab-separated layout for scripts")]
+        porcelain: bool,
+        #[arg(long, help = "show the complete blob ids instead of their prefix")]
+        full_blob_id: bool,
+        #[arg(long, conflicts_with_all = ["porcelain", "json"], help = "print only the number of buckets, or of the objects of the bucket")]
+        count: bool,
+    },
+
+    #[command(
+        name = "mb",
+        about = r#"create bucket
+    mb s3://<bucket>
+create several buckets, one transaction each
+    mb s3://<bucket> s3://<bucket> ...
+create the bucket with tags
+    mb s3://<bucket> --tag <key>=<value> [--tag <key>=<value>] ..."#
+    )]
+    CreateBucket {
+        #[arg(required = true)]
+        buckets: Vec<String>,
+        #[arg(long = "tag", help = "tag the bucket with <key>=<value>, may be repeated")]
+        tags: Vec<String>,
+    },
+
+    #[command(
+        name = "rb",
+        about = r#"delete an empty bucket
+    rb s3://<bucket>
+delete several buckets, one transaction each
+    rb s3://<bucket> s3://<bucket> ...
+delete the objects of the bucket first, then the bucket
+    rb -r s3://<bucket>"#
+    )]
+    DeleteBucket {
+        #[arg(required = true)]
+        buckets: Vec<String>,
+        #[arg(short, long, help = "delete the objects of the bucket first, one transaction each")]
+        recursive: bool,
+    },
+
+    #[command(
+        name = "rename-bucket",
+        about = r#"rename the bucket, copying the objects metadata into a new bucket, the blobs are not re-uploaded
+    rename-bucket s3://<bucket> s3://<new bucket> [--dry-run]
+copy up to <n> objects in one transaction, 20 by default
+    rename-bucket s3://<bucket> s3://<new bucket> --batch-size <n>"#
+    )]
+    RenameBucket {
+        old: String,
+        new: String,
+        #[arg(long, default_value_t = 20, help = "number of objects copied in one transaction")]
+        batch_size: usize,
+        #[arg(long, help = "print the planned actions without executing them")]
+        dry_run: bool,
+    },
+
+    #[command(about = r#"rename the objects under a key prefix to another prefix, the metadata is moved and the blobs are kept
+    reprefix s3://<bucket> --from <prefix> --to <prefix> [--dry-run]
+up to <n> objects move in one transaction, all or none of them
+    reprefix s3://<bucket> --from <prefix> --to <prefix> --batch-size <n>"#)]
+    Reprefix {
+        uri: String,
+        #[arg(long, help = "the key prefix of the objects to move, e.g. logs/")]
+        from: String,
+        #[arg(long, help = "the key prefix replacing it, e.g. archive/logs/")]
+        to: String,
+        #[arg(long, default_value_t = 20, help = "number of objects moved in one transaction")]
+        batch_size: usize,
+        #[arg(long, help = "print the planned moves without executing them")]
+        dry_run: bool,
+    },
+
+    #[command(about = r#"upload the file with specify object name
+    put <file> s3://<bucket>/<object>
+upload the file as the same file name
+    put <file> s3://<bucket>
+upload the file under another name, into the bucket or a folder of it
+    put <file> s3://<bucket>[/<folder>/] --name <object>
+download the url first, then upload it
+    put https://<host>/<path> s3://<bucket>[/<object>]
+create the bucket first if it does not exist
+    put <file> s3://<bucket>/<object> --create-bucket
+overwrite an existing object without asking in the shell mode, like any prompt with -y/--yes
+    put <file> s3://<bucket>/<object> -y
+store the file compressed, tagged content-encoding=<gzip|zstd> for get
+    put <file> s3://<bucket>/<object> --compress gzip
+upload the files of the folder under the prefix, uploading <n> files ahead of the transactions
+    put -r <folder> s3://<bucket>[/<prefix>] [--overlap <n>]
+set the content-type tag, guessed from the file extension by default
+    put <file> s3://<bucket>/<object> --content-type <mime type>
+tag the object, on top of the tags of the bucket it inherits unless --no-inherit
+    put <file> s3://<bucket>/<object> --tag <key>=<value> ... [--no-inherit]"#)]
+    Put {
+        file: String,
+        uri: String,
+        #[arg(long, help = "create the bucket first if it does not exist")]
+        create_bucket: bool,
+        #[arg(long, help = "mime type stored in the content-type tag, guessed from the file extension by default")]
+        content_type: Option<String>,
+        #[arg(long, help = "print the complete blob id instead of its prefix")]
+        full_blob_id: bool,
+        #[arg(long = "tag", help = "tag the object with <key>=<value>, may be repeated, wins over a bucket tag of the same key")]
+        tags: Vec<String>,
+        #[arg(long, help = "do not copy the tags of the bucket to the object")]
+        no_inherit: bool,
+        #[arg(long, value_parser = utils::COMPRESSIONS, help = "compress the file before the upload, get decompresses it")]
+        compress: Option<String>,
+        #[arg(long, help = "reuse the walrus blob of the same content put with --dedup before, noted in ~/.suis3/blobs")]
+        dedup: bool,
+        #[arg(short, long, conflicts_with_all = ["content_type", "compress", "dedup"], help = "upload the files of the folder, keyed by their path under it")]
+        recursive: bool,
+        #[arg(long, default_value_t = 1, help = "with -r, number of walrus uploads run ahead of the metadata transaction of the current file, 0 for none")]
+        overlap: usize,
+        #[arg(long, conflicts_with = "recursive", help = "object name instead of the file name, the uri then ends at the bucket or a folder")]
+        name: Option<String>,
+        #[arg(long, conflicts_with = "recursive", help = "keep the object as it is and store the file as its next version, <object>.v<n>")]
+        versioned: bool,
+        #[arg(long, help = "overwrite the object even if it is locked")]
+        force_unlock: bool,
+    },
+
+    #[command(about = r#"download the object
+    get s3://<bucket>/<object> <file>
+download the object to current folder
+    get s3://<bucket>/<object>
+download the object to the same path as its key, under the current or given folder
+    get s3://<bucket>/<object> [<folder>] --preserve-path
+download all the objects of the bucket into the folder, keeping the key hierarchy
+    get -r s3://<bucket> <folder> [--concurrency <n>]
+show what would be downloaded without downloading
+    get -r s3://<bucket> <folder> --dry-run
+never overwrite an existing local file, or always overwrite it
+    get s3://<bucket>[/<object>] [<file or folder>] [-r] --no-clobber/-n | --force
+decompress the blob, objects put with --compress are decompressed without it
+    get s3://<bucket>/<object> [<file>] --decompress <gzip|zstd>
+ask walrus again about a blob it reports unavailable, and download it again if it is still stored
+    get s3://<bucket>[/<object>] [<file or folder>] [-r] --retry-on-expiry
+download an older version of an object put with --versioned, the latest one without it
+    get s3://<bucket>/<object> [<file>] --version <n>"#)]
+    Get {
+        uri: String,
+        file: Option<String>,
+        #[arg(short, long, help = "download all the objects of the bucket")]
+        recursive: bool,
+        #[arg(long, default_value_t = 4, help = "number of parallel downloads with -r")]
+        concurrency: usize,
+        #[arg(long, help = "with -r, print the planned downloads without executing them")]
+        dry_run: bool,
+        #[arg(long, help = "recreate the folders of the object key under the destination folder")]
+        preserve_path: bool,
+        #[arg(short = 'n', long, conflicts_with = "force", help = "skip the objects whose local file already exists")]
+        no_clobber: bool,
+        #[arg(long, help = "overwrite the local files, with -r also the ones already present with the same size")]
+        force: bool,
+        #[arg(long, value_parser = utils::COMPRESSIONS, help = "decompress the blob, by default the compression put recorded in the content-encoding tag")]
+        decompress: Option<String>,
+        #[arg(long, help = "when walrus reports the blob unavailable, check its status and download again if it is still stored")]
+        retry_on_expiry: bool,
+        #[arg(long, conflicts_with = "recursive", help = "version of an object put with --versioned, 1 is the first")]
+        version: Option<u64>,
+    },
+
+    #[command(about = r#"copy between a local file and an object, the direction follows the arguments
+upload the local file
+    cp <file> s3://<bucket>[/<object>]
+download the object
+    cp s3://<bucket>/<object> <file or folder>
+copy the object metadata, the blob is shared
+    cp s3://<bucket>/<object> s3://<bucket>[/<object>] [--preserve]"#)]
+    Cp {
+        src: String,
+        dst: String,
+        #[arg(long, help = "between two objects, also copy the tags and keep the source write time in the original-write-ts tag")]
+        preserve: bool,
+    },
+
+    #[command(about = r#"move the object metadata to another key or bucket, with its tags
+    mv s3://<bucket>/<object> s3://<bucket>[/<object>] [--no-preserve]"#)]
+    Mv {
+        src: String,
+        dst: String,
+        #[arg(long, help = "keep only the content-type tag, like cp without --preserve")]
+        no_preserve: bool,
+    },
+
+    #[command(about = r#"store again the blobs of the objects ending within 2 epochs of the current one, and point the objects at the new blobs
+    repair s3://<bucket> [--within <epochs>] [--concurrency <n>]
+show the objects which would be repaired
+    repair s3://<bucket> --dry-run"#)]
+    Repair {
+        uri: String,
+        #[arg(long, default_value_t = 2, help = "repair the blobs ending at most this many epochs after the current one")]
+        within: u64,
+        #[arg(long, default_value_t = 4, help = "number of blobs downloaded and stored again in parallel")]
+        concurrency: usize,
+        #[arg(long, help = "print the expiring objects without storing anything")]
+        dry_run: bool,
+    },
+
+    #[command(about = r#"restore the object metadata pointing at an existing walrus blob, without re-uploading
+    restore s3://<bucket>/<object> <blob id> <size> <end epoch>"#)]
+    Restore {
+        uri: String,
+        blob_id: String,
+        size: u64,
+        epoch: u64,
+    },
+
+    #[command(name = "blob-status", about = r#"show what walrus reports on a blob, its end epoch among others, whether an object points to it or not
+    blob-status <blob id>"#)]
+    BlobStatus { blob_id: String },
+
+    #[command(about = r#"print a public link to the object content, served by the walrus aggregator by blob id
+anyone with the link can read the object, until its blob expires
+    share s3://<bucket>/<object>"#)]
+    Share { uri: String },
+
+    #[command(about = r#"display the object content, up to 10 MiB
+    cat s3://<bucket>/<object>
+display a larger object
+    cat s3://<bucket>/<object> --limit <bytes>
+    cat s3://<bucket>/<object> --all
+indent json, and color json, markdown or code on a terminal
+    cat s3://<bucket>/<object> --pretty"#)]
+    Cat {
+        uri: String,
+        #[arg(long, conflicts_with = "all", help = "largest object size to print in bytes, 10 MiB by default")]
+        limit: Option<u64>,
+        #[arg(long, help = "print the object whatever its size")]
+        all: bool,
+        #[arg(long, help = "render the object by its content-type tag or name: indent json, color json, markdown or code on a terminal; anything else is printed raw")]
+        pretty: bool,
+    },
+
+    #[command(
+        visible_alias = "rm",
+        about = r#"delete the object
+    del s3://<bucket>/<object>
+delete the object even if it is locked
+    del s3://<bucket>/<object> --force-unlock"#
+    )]
+    Del {
+        uri: String,
+        #[arg(long, help = "delete the object even if it is locked")]
+        force_unlock: bool,
+    },
+
+    #[command(about = r#"in the shell, list the objects of the bucket by number and delete the ones picked, more than confirm_threshold after a confirmation
+    select s3://<bucket>
+the entered bucket without a uri
+    select"#)]
+    Select { uri: Option<String> },
+
+    #[command(about = r#"list the bucket names again into the completion cache, and the object names of every bucket
+    refresh
+only the object names of the bucket, the entered one in the shell
+    refresh s3://<bucket>"#)]
+    Refresh { uri: Option<String> },
+
+    #[command(about = r#"print the uris completing a partial one, one per line, from the cache of names listed less than 10 minutes ago
+    complete s3://<partial bucket>
+    complete s3://<bucket>/<partial object>"#)]
+    Complete { word: Option<String> },
+
+    #[command(about = r#"lock the object against del, with the locked=true tag
+    lock s3://<bucket>/<object>"#)]
+    Lock { uri: String },
+
+    #[command(about = r#"remove the lock of the object
+    unlock s3://<bucket>/<object>"#)]
+    Unlock { uri: String },
+
+    #[command(about = r#"copy the tags of an object to another, keeping the other tags of the destination
+    copy-tags s3://<bucket>/<object> s3://<bucket>/<object> [--merge]
+replace all the tags of the destination
+    copy-tags s3://<bucket>/<object> s3://<bucket>/<object> --replace"#)]
+    CopyTags {
+        src: String,
+        dst: String,
+        #[arg(long, conflicts_with = "replace", help = "keep the destination tags, a source tag wins on the same key (default)")]
+        merge: bool,
+        #[arg(long, help = "drop the destination tags not on the source")]
+        replace: bool,
+    },
+
+    #[command(
+        name = "history",
+        visible_alias = "logs",
+        about = r#"show the recent write operations on the bucket, oldest first
+    history s3://<bucket> [--limit <n>]
+look further back than the newest 1000 transactions of the package
+    history s3://<bucket> --scan <n>"#
     )]
-    List { uri: Option<String> },
+    History {
+        uri: String,
+        #[arg(long, default_value_t = 20, help = "show at most <n> operations")]
+        limit: usize,
+        #[arg(long, default_value_t = operations::HISTORY_SCAN, help = "read at most <n> transactions of the package, newest first")]
+        scan: usize,
+    },
+
+    #[command(about = r#"export the objects metadata of the bucket as newline-delimited JSON
+    export s3://<bucket> [<file>]"#)]
+    Export { uri: String, out: Option<String> },
+
+    #[command(about = r#"recreate the objects metadata from an export, pointing at the existing blobs
+    import s3://<bucket> <file>
+overwrite the objects which already exist
+    import s3://<bucket> <file> --force
+create up to <n> objects per transaction, 20 by default
+    import s3://<bucket> <file> --batch-size <n>
+show what would be imported without importing
+    import s3://<bucket> <file> --dry-run"#)]
+    Import {
+        uri: String,
+        file: String,
+        #[arg(long, help = "overwrite the objects which already exist")]
+        force: bool,
+        #[arg(long, default_value_t = 20, help = "number of objects created in one transaction")]
+        batch_size: usize,
+        #[arg(long, help = "print the planned imports without executing them")]
+        dry_run: bool,
+    },
+
+    #[command(about = r#"tag operations
+list tags of the bucket or the object
+    tag ls/list s3://<bucket>[/<object>]
+add tags to the object
+    tag add/put s3://<bucket>/<object>  <key>=<value> ...
+add the same tags to several objects, or to the objects matching * and ?, a batch per transaction
+    tag add/put s3://<bucket>/<object> s3://<bucket>/<object> ... <key>=<value> ... [--batch-size <n>]
+    tag add/put 's3://<bucket>/logs/*' <key>=<value> ...
+add the tags listed in a file, one <key>=<value> per line
+    tag add/put s3://<bucket>/<object> --from-file <file>
+remove tags from the object, the locked, content-type, content-encoding and latest-version tags stay
+    tag del/rm s3://<bucket>/<object>
+remove the locked tag as well
+    tag del/rm s3://<bucket>/<object> --force-unlock"#)]
+    Tag {
+        #[arg(value_enum)]
+        action: TagAction,
+        uri: String,
+        tags: Vec<String>,
+        #[arg(long, help = "with add/put, also the <key>=<value> lines of the file, blank and # lines are skipped")]
+        from_file: Option<String>,
+        #[arg(long, default_value_t = 20, help = "with add/put of several objects, number of objects tagged in one transaction")]
+        batch_size: usize,
+        #[arg(long, help = "with del/rm, also remove the locked tag")]
+        force_unlock: bool,
+    },
+
+    #[command(about = r#"config operations, stored in ~/.suis3/config.toml
+list the effective config of this session
+    config ls/list
+show one config value
+    config get <key>
+change or remove one config value
+    config set <key> <value>
+    config unset <key>
+keys: network, rpc_url, package_id, buckets_root, gas_budget, walrus_bin, walrus_timeout, aggregator_url, download_dir,
+confirm_threshold"#)]
+    Config {
+        #[arg(value_enum)]
+        action: ConfigAction,
+        key: Option<String>,
+        value: Option<String>,
+    },
+
+    #[cfg(feature = "serve")]
+    #[command(about = r#"serve the bucket read-only over a local S3-compatible endpoint, until stopped
+    serve s3://<bucket> [--port <port>]
+e.g. aws s3 ls s3://<bucket> --endpoint-url http://127.0.0.1:<port>"#)]
+    Serve {
+        bucket: String,
+        #[arg(long, default_value_t = 9100, help = "local port to listen on")]
+        port: u16,
+    },
+
+    #[command(about = r#"check the walrus cli, the wallet and its funds, the rpc and the suis3 objects, with hints for what fails
+    doctor"#)]
+    Doctor,
+
+    #[command(name = "quit/exit", about = "quit the programe")]
+    Quit,
+    // #[command(name = "help", about = "show s3 command usage")]
+    // Help,
+}
+
+#[derive(ValueEnum, PartialEq, Debug, Clone)]
+pub enum ConfigAction {
+    List,
+    Ls,
+    Get,
+    Set,
+    Unset,
+}
+
+#[derive(ValueEnum, PartialEq, Debug, Clone)]
+pub enum TagAction {
+    List,
+    Ls,
+    Add,
+    Put,
+    Del,
+    Rm,
+}
+
+fn print_digest(out: &mut dyn Write, digest: &TransactionDigest) {
+    statusln!(out, "Transaction: {}", digest);
+    statusln!(out, "Explorer: {}", utils::explorer_tx_url(digest));
+}
+
+// one line per bucket, with its tags if asked
+// the object count and total size of each bucket, None for the ones which cannot be read
+async fn bucket_stats(buckets: &[operations::BucketInfo]) -> Vec<Option<(usize, u64)>> {
+    let results = stream::iter(buckets.iter().map(|b| async move {
+        operations::get_bucket_objects(b.name.clone()).await
+    }))
+    .buffered(STATS_CONCURRENCY)
+    .collect::<Vec<_>>()
+    .await;
+
+    buckets
+        .iter()
+        .zip(results)
+        .map(|(b, result)| match result {
+            Err(e) => {
+                errln!("Failed: suis3://{}: {}", b.name, e);
+                None
+            }
+            Ok(list) => Some((list.objects.len(), list.objects.iter().map(|o| o.size).sum())),
+        })
+        .collect()
+}
+
+async fn print_bucket_count(out: &mut dyn Write) {
+    match operations::list_buckets().await {
+        Err(e) => failln!(e),
+        Ok(buckets) => outln!(out, "{}", buckets.len()),
+    }
+}
+
+async fn print_buckets(out: &mut dyn Write, with_tags: bool, porcelain: bool, stats: bool) {
+    let buckets = match operations::list_buckets().await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(v) => v,
+    };
+    let tags = if with_tags {
+        let names = buckets.iter().map(|b| b.name.clone()).collect::<Vec<_>>();
+        match operations::list_buckets_tags(&names).await {
+            Err(e) => {
+                failln!(e);
+                return;
+            }
+            Ok(tags) => Some(tags),
+        }
+    } else {
+        None
+    };
+    cache::save_buckets(buckets.iter().map(|b| b.name.clone()).collect());
+    let stats = match stats {
+        true => Some(bucket_stats(&buckets).await),
+        false => None,
+    };
+
+    if porcelain {
+        for (i, bi) in buckets.iter().enumerate() {
+            let tags = tags.as_ref().map(|t| t[i].as_slice());
+            let record = match &stats {
+                None => porcelain::bucket(bi, tags),
+                Some(stats) => porcelain::bucket_stats(bi, tags.unwrap_or(&[]), stats[i]),
+            };
+            let _ = write!(out, "{}", record);
+        }
+        return;
+    }
+
+    match (&tags, &stats) {
+        (None, _) => outln!(out, "TIME\t\t\t\tBUCKET NAME"),
+        (Some(_), None) => outln!(out, "TIME\t\t\t\tBUCKET NAME\tTAGS"),
+        (Some(_), Some(_)) => outln!(out, "TIME\t\t\t\tBUCKET NAME\tOBJECTS\tSIZE\tTAGS"),
+    }
+    for (i, bi) in buckets.iter().enumerate() {
+        let date_time = utils::display_time(bi.create_ts);
+        match (&tags, &stats) {
+            (None, _) => outln!(out, "{}\t{}", date_time, bi.name),
+            (Some(tags), None) => outln!(out, "{}\t{}\t{}", date_time, bi.name, tags[i].join(" ")),
+            (Some(tags), Some(stats)) => {
+                let (count, size) = match stats[i] {
+                    Some((count, size)) => (count.to_string(), size.to_string()),
+                    None => ("-".to_owned(), "-".to_owned()),
+                };
+                outln!(out, "{}\t{}\t{}\t{}\t{}", date_time, bi.name, count, size, tags[i].join(" "));
+            }
+        }
+    }
+}
+
+fn print_more_objects(out: &mut dyn Write, list: &operations::BucketObjectsList) {
+    if let Some(last) = list.objects.last() {
+        statusln!(out, "More objects available, continue with --start-after {}", last.uri);
+    }
+}
+
+// a record of the --json output, one line, or indented with --json-pretty
+fn print_json<T: serde::Serialize>(out: &mut dyn Write, record: &T) {
+    let json = match settings::get().json_pretty {
+        true => serde_json::to_string_pretty(record),
+        false => serde_json::to_string(record),
+    };
+    outln!(out, "{}", json.unwrap());
+    // each record goes out right away, a consumer reading the pipe can start on it
+    let _ = out.flush();
+}
+
+// the same hint for the --json and --porcelain listings, kept out of their records
+fn note_more_objects(list: &operations::BucketObjectsList) {
+    if let Some(last) = list.objects.last() {
+        eprintln!("More objects available, continue with --start-after {}", last.uri);
+    }
+}
+
+// on stderr, the listing keeps just its header
+fn note_empty_bucket(bucket_name: &str) {
+    if !settings::get().quiet {
+        eprintln!("Bucket suis3://{} is empty", bucket_name);
+    }
+}
+
+// ask on the terminal, anything but y/yes is a no. --yes answers every question,
+// without a terminal to ask on the answer is no and the command fails
+fn confirm(question: &str) -> bool {
+    if settings::get().yes {
+        return true;
+    }
+    match ask(&format!("{} [y/N] ", question)) {
+        Some(answer) => matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"),
+        None => {
+            errln!("{} No terminal to ask on, pass --yes to confirm.", question);
+            false
+        }
+    }
+}
+
+// the line answered on the terminal, none without a terminal
+fn ask(question: &str) -> Option<String> {
+    let mut tty = fs::OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+    let _ = tty.write_all(question.as_bytes());
+    let _ = tty.flush();
+    let mut answer = String::new();
+    let _ = BufReader::new(&tty).read_line(&mut answer);
+    Some(answer)
+}
+
+// refresh: the bucket names and the object names of every bucket, or of the bucket of the uri
+async fn refresh(out: &mut dyn Write, uri: Option<String>) {
+    if settings::get().no_cache {
+        errln!("--no-cache leaves the completion cache alone, refresh has nothing to do");
+        return;
+    }
+    let buckets = match uri {
+        Some(uri) => match parse_uri(&uri) {
+            Some(caps) if caps.object.is_empty() => vec![caps.bucket],
+            _ => {
+                errln!("SUIS3 bucket format error.");
+                return;
+            }
+        },
+        None => match cache::refresh_buckets().await {
+            Err(e) => {
+                failln!(e);
+                return;
+            }
+            Ok(names) => {
+                statusln!(out, "{} buckets cached", names.len());
+                names
+            }
+        },
+    };
+    for bucket_name in buckets {
+        match cache::refresh_objects(&bucket_name).await {
+            Err(e) => failln!(e),
+            Ok(keys) => statusln!(out, "suis3://{}: {} objects cached", bucket_name, keys.len()),
+        }
+    }
+}
+
+// select: the objects picked by their number in the listing, deleted one transaction each
+async fn select_delete(out: &mut dyn Write, uri: Option<String>) {
+    if !settings::get().interactive {
+        errln!("select picks the objects on the terminal, it runs in the shell only, use del outside");
+        return;
+    }
+    let Some(caps) = uri.as_deref().and_then(parse_uri) else {
+        errln!("SUIS3 object format error.");
+        return;
+    };
+    let bucket_name = caps.bucket.as_str();
+    let list = match operations::get_bucket_objects(bucket_name.to_owned()).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(list) => list,
+    };
+    if list.objects.is_empty() {
+        note_empty_bucket(bucket_name);
+        return;
+    }
+    for (i, obj) in list.objects.iter().enumerate() {
+        outln!(out, "{:>4}  {}\t{}", i + 1, obj.uri, obj.size);
+    }
+    let _ = out.flush();
+
+    let Some(answer) = ask("Numbers of the objects to delete, separated by spaces (none to cancel): ") else {
+        errln!("No terminal to ask on.");
+        return;
+    };
+    let mut picked = Vec::new();
+    for word in answer.split_whitespace() {
+        match word.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= list.objects.len() => picked.push(n - 1),
+            _ => {
+                errln!("{} is not a number of the listing, nothing was deleted", word);
+                return;
+            }
+        }
+    }
+    picked.sort();
+    picked.dedup();
+    if picked.is_empty() {
+        statusln!(out, "Nothing selected");
+        return;
+    }
+    // a few objects go without asking, like rb -r, more than confirm_threshold need a yes
+    let threshold = settings::get().config.confirm_threshold;
+    let names = picked.iter().map(|i| list.objects[*i].uri.as_str()).collect::<Vec<_>>().join(" ");
+    if picked.len() as u64 > threshold && !confirm(&format!("Delete {} object(s) of suis3://{}: {}?", picked.len(), bucket_name, names)) {
+        return;
+    }
+
+    let (mut done, mut failed) = (0, 0);
+    for i in picked {
+        let obj = &list.objects[i];
+        if let Err(e) = operations::ensure_unlocked(bucket_name, &obj.uri, &obj.tags, false) {
+            errln!("Failed: {}", e);
+            failed += 1;
+            continue;
+        }
+        match operations::delete_object(bucket_name.to_owned(), obj.uri.clone()).await {
+            Err(e) => {
+                errln!("Failed: suis3://{}{}: {}", bucket_name, obj.uri, e);
+                failed += 1;
+            }
+            Ok(digest) => {
+                statusln!(out, "Deleted metadata for suis3://{}{} (blob id: {}, expires at epoch {}; recover with `restore`)",
+                          bucket_name, obj.uri, obj.walrus_blob_id, obj.walrus_epoch_till);
+                print_digest(out, &digest);
+                done += 1;
+            }
+        }
+    }
+    statusln!(out, "Deleted {}, failed {}", done, failed);
+}
+
+// the flags of put, as given on the command line
+#[derive(Default)]
+struct PutOptions {
+    create_bucket: bool,
+    content_type: Option<String>,
+    full_blob_id: bool,
+    tags: Vec<String>,
+    no_inherit: bool,
+    compress: Option<String>,
+    dedup: bool,
+    versioned: bool,
+    force_unlock: bool,
+    // put -r only
+    overlap: usize,
+}
+
+// the flags of get, as given on the command line
+#[derive(Default)]
+struct GetOptions {
+    preserve_path: bool,
+    no_clobber: bool,
+    decompress: Option<String>,
+    retry_on_expiry: bool,
+    version: Option<u64>,
+    // get -r only
+    concurrency: usize,
+    dry_run: bool,
+    force: bool,
+}
+
+// an http(s) source is downloaded to a temporary folder first, under the last segment of its path
+async fn put_file(out: &mut dyn Write, file: String, uri: String, options: PutOptions) {
+    if !file.starts_with("http://") && !file.starts_with("https://") {
+        put_local_file(out, file, uri, options).await;
+        return;
+    }
+    let name = url::Url::parse(&file)
+        .ok()
+        .and_then(|url| url.path_segments()?.filter(|s| s.len() > 0).last().map(|s| s.to_owned()))
+        .unwrap_or("index.html".to_owned());
+    let dir = env::temp_dir().join(format!("suis3_put_{}", std::process::id()));
+    let local = dir.join(&name);
+
+    let start = Instant::now();
+    let downloaded = match fs::create_dir_all(&dir) {
+        Err(e) => Err(e.into()),
+        Ok(()) => utils::download_url(&file, &local, URL_PUT_MAX_SIZE).await,
+    };
+    match downloaded {
+        Err(e) => failln!(e),
+        Ok(size) => {
+            statusln!(out, "Downloaded {} bytes from {} in {:.2?}", size, file, start.elapsed());
+            put_local_file(out, local.to_str().unwrap().to_owned(), uri, options).await;
+        }
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
+
+// fail a multi-step command up front when the rpc, or walrus if it needs it, cannot be reached
+async fn preflight(with_walrus: bool) -> bool {
+    if settings::get().no_preflight {
+        return true;
+    }
+    match utils::preflight(with_walrus).await {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!("Preflight check failed, nothing was done: {}", e);
+            eprintln!("Retry once it is reachable, or skip the check with --no-preflight");
+            fail_with(e.exit_code());
+            false
+        }
+    }
+}
+
+// create the bucket if missing, false when it cannot be
+async fn ensure_bucket(out: &mut dyn Write, bucket_name: &str) -> bool {
+    match operations::bucket_exists(bucket_name).await {
+        Err(e) => {
+            failln!(e);
+            false
+        }
+        Ok(true) => true,
+        Ok(false) => match operations::create_bucket(bucket_name.to_owned(), Vec::new()).await {
+            Err(e) => {
+                failln!(e);
+                false
+            }
+            Ok(digest) => {
+                statusln!(out, "Created bucket: suis3://{}", bucket_name);
+                print_digest(out, &digest);
+                true
+            }
+        },
+    }
+}
+
+// the files under the folder, in path order
+fn list_files(dir: &Path) -> Result<Vec<std::path::PathBuf>, std::io::Error> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(list_files(&path)?);
+        } else if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// put -r: the walrus uploads run up to `overlap` files ahead of the create_object transactions,
+// which go one at a time since they all mutate the buckets root
+async fn put_folder(out: &mut dyn Write, dir: String, uri: String, options: PutOptions) {
+    let PutOptions { create_bucket, full_blob_id, tags, no_inherit, force_unlock, overlap, .. } = options;
+    let Some(caps) = parse_uri(&uri) else {
+        errln!("SUIS3 object format error.");
+        return;
+    };
+    let bucket_name = caps.bucket.as_str();
+    let prefix = caps.object.trim_end_matches('/').to_owned() + "/";
+    let files = match list_files(Path::new(&dir)) {
+        Err(e) => {
+            errln!("Cannot read the folder {}: {}", dir, e);
+            return;
+        }
+        Ok(files) => files,
+    };
+    if let Err(e) = walrus::ensure_walrus_available() {
+        failln!(e);
+        return;
+    }
+    if !preflight(true).await {
+        return;
+    }
+    if create_bucket && !ensure_bucket(out, bucket_name).await {
+        return;
+    }
+    let bucket_tags = if no_inherit {
+        Vec::new()
+    } else {
+        match operations::list_bucket_tags(bucket_name.to_owned()).await {
+            Err(e) => {
+                failln!(e);
+                return;
+            }
+            Ok(tags) => tags,
+        }
+    };
+
+    // a locked object is only overwritten with --force-unlock, like del
+    let existing = if force_unlock {
+        Vec::new()
+    } else {
+        match operations::get_bucket_objects(bucket_name.to_owned()).await {
+            Err(e) => {
+                failln!(e);
+                return;
+            }
+            Ok(list) => list.objects,
+        }
+    };
+    let key_of = |path: &Path| {
+        let relative = path.strip_prefix(&dir).unwrap_or(path);
+        prefix.clone() + &relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+    };
+    let count = files.len();
+    let mut failed = 0;
+    let files = files
+        .into_iter()
+        .filter(|path| {
+            let key = key_of(path);
+            let unlocked = match existing.iter().find(|obj| obj.uri == key) {
+                Some(obj) => operations::ensure_unlocked(bucket_name, &key, &obj.tags, force_unlock),
+                None => Ok(()),
+            };
+            match unlocked {
+                Err(e) => {
+                    errln!("Failed: {} or put with --force-unlock", e);
+                    failed += 1;
+                    false
+                }
+                Ok(()) => true,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // buffered starts the next uploads while the transaction of the current file is awaited. the upload of
+    // that file is still held by the stream, so `overlap` more are needed to run any ahead, 0 runs none
+    let mut uploads = stream::iter(files.into_iter().map(|path| async move {
+        let file = path.to_str().unwrap().to_owned();
+        let result = tokio::task::spawn_blocking(move || walrus::backend().upload(&file))
+            .await
+            .map_err(anyhow::Error::from)
+            .and_then(|r| r);
+        (path, result)
+    }))
+    .buffered(overlap + 1);
+
+    let mut done = 0;
+    while let Some((path, result)) = uploads.next().await {
+        let key = key_of(&path);
+        let mut meta = match result {
+            Err(e) => {
+                errln!("Failed: {}: {}", path.display(), e);
+                failed += 1;
+                continue;
+            }
+            Ok(meta) => meta,
+        };
+        let mut own_tags = tags.clone();
+        own_tags.push(format!("{}={}", operations::CONTENT_TYPE_TAG, utils::content_type_of(path.to_str().unwrap())));
+        meta.tags = operations::merge_tags(&bucket_tags, &own_tags);
+
+        match operations::create_object(bucket_name, &key, &meta).await {
+            Err(e) => {
+                errln!("Failed: suis3://{}{}: {}", bucket_name, key, e);
+                failed += 1;
+            }
+            Ok(digest) => {
+                done += 1;
+                statusln!(out, "Put {} as suis3://{}{} ({}/{}), blob id: {}", path.display(), bucket_name, key, done + failed, count,
+                          blob_id_display(&meta.walrus_blob_id, full_blob_id));
+                print_digest(out, &digest);
+            }
+        }
+    }
+    statusln!(out, "Put {}, failed {}", done, failed);
+}
+
+async fn put_local_file(out: &mut dyn Write, file: String, uri: String, options: PutOptions) {
+    let PutOptions { create_bucket, content_type, full_blob_id, tags, no_inherit, compress, dedup, versioned, force_unlock, .. } = options;
+    let Some(caps) = parse_uri(&uri) else {
+        errln!("SUIS3 object format error.");
+        return;
+    };
+    let bucket_name = caps.bucket.as_str();
+    let mut obj_name = caps.object.clone();
+
+    if obj_name.len() == 0 || obj_name.ends_with('/') {
+        // upload into the "folder" with the local file name
+        let path = Path::new(&file);
+        let filename = path.file_name().unwrap();
+        if obj_name.len() == 0 {
+            obj_name = "/".to_owned();
+        }
+        obj_name = obj_name + filename.to_str().unwrap();
+    }
+
+    if create_bucket && !ensure_bucket(out, bucket_name).await {
+        return;
+    }
+
+    // --versioned: a new object is version 1 and records it, an existing one gets the next version beside it.
+    // the next version is read then written, two puts of the key at once may both store the same one
+    let mut own_tags = tags;
+    let mut versioned_base = None;
+    if versioned {
+        match operations::get_object(bucket_name.to_owned(), obj_name.clone()).await {
+            Err(e) if !matches!(e.downcast_ref(), Some(operations::SuiS3Error::ObjectNotFound(..))) => {
+                failln!(e);
+                return;
+            }
+            Err(_) => own_tags.push(format!("{}=1", operations::LATEST_VERSION_TAG)),
+            Ok(meta) => {
+                let version = operations::latest_version(&meta.tags) + 1;
+                versioned_base = Some((obj_name.clone(), meta.tags, version));
+                obj_name = operations::version_key(&obj_name, version);
+            }
+        }
+    }
+
+    // a missing object fails the lookup, which is fine to upload. a locked one is only overwritten with --force-unlock, like del
+    if let Ok(meta) = operations::get_object(bucket_name.to_owned(), obj_name.clone()).await {
+        if let Err(e) = operations::ensure_unlocked(bucket_name, &obj_name, &meta.tags, force_unlock) {
+            errln!("{} or put with --force-unlock", e);
+            return;
+        }
+        // a plain put over a versioned key still points get at its versions
+        if let Some(latest) = operations::tag_value(&meta.tags, operations::LATEST_VERSION_TAG).filter(|_| !versioned) {
+            own_tags.push(format!("{}={}", operations::LATEST_VERSION_TAG, latest));
+        }
+        if settings::get().interactive {
+            let question = format!("Object suis3://{}{} already exists (size {}, blob {}). Overwrite?",
+                                   bucket_name, obj_name, meta.size, blob_id_display(&meta.walrus_blob_id, full_blob_id));
+            if !confirm(&question) {
+                statusln!(out, "Skipped: suis3://{}{}", bucket_name, obj_name);
+                return;
+            }
+        }
+    }
+
+    // the bucket tags are the defaults, the object's own tags win on the same key
+    let bucket_tags = if no_inherit {
+        Vec::new()
+    } else {
+        match operations::list_bucket_tags(bucket_name.to_owned()).await {
+            Err(e) => {
+                failln!(e);
+                return;
+            }
+            Ok(tags) => tags,
+        }
+    };
+    let content_type = content_type.unwrap_or(utils::content_type_of(&file).to_owned());
+    own_tags.push(format!("{}={}", operations::CONTENT_TYPE_TAG, content_type));
+
+    // the compressed copy is uploaded, the content type stays the one of the file
+    let mut upload = file.clone();
+    if let Some(compression) = &compress {
+        let compressed = env::temp_dir().join(format!("suis3_compress_{}", std::process::id()));
+        if let Err(e) = utils::compress_file(Path::new(&file), &compressed, compression) {
+            failln!(e);
+            return;
+        }
+        upload = compressed.to_str().unwrap().to_owned();
+        own_tags.push(format!("{}={}", operations::CONTENT_ENCODING_TAG, compression));
+    }
+
+    let tags = operations::merge_tags(&bucket_tags, &own_tags);
+    let indexed = if dedup { operations::find_indexed_blob(&upload) } else { None };
+    let reused = indexed.is_some();
+    let result = match indexed {
+        Some(meta) => {
+            let meta = walrus::BlobMeta { tags, ..meta };
+            operations::create_object(bucket_name, obj_name.as_str(), &meta).await.map(|digest| (meta, digest))
+        }
+        None => operations::put_object(bucket_name, obj_name.as_str(), &upload, tags).await,
+    };
+    match result {
+        Err(e) => failln!(e),
+        Ok((meta, digest)) => {
+            if reused {
+                statusln!(out, "Reused the stored blob of the same content, till epoch {}", meta.walrus_epoch_till);
+            } else if dedup {
+                if let Err(e) = operations::index_blob(&upload, &meta) {
+                    log::warn!("Cannot note the blob for --dedup: {}", e);
+                }
+            }
+            statusln!(out, "Blob id: {}", blob_id_display(&meta.walrus_blob_id, full_blob_id));
+            print_digest(out, &digest);
+            // the object points get at the new version
+            if let Some((base, base_tags, version)) = versioned_base {
+                let latest = format!("{}={}", operations::LATEST_VERSION_TAG, version);
+                match operations::tag_object(bucket_name.to_owned(), base.clone(), operations::merge_tags(&base_tags, &[latest])).await {
+                    Err(e) => errln!("Stored version {} as suis3://{}{}, but suis3://{}{} does not point at it: {}", version, bucket_name, obj_name,
+                                     bucket_name, base, e),
+                    Ok(digest) => {
+                        statusln!(out, "Version {} of suis3://{}{}", version, bucket_name, base);
+                        print_digest(out, &digest);
+                    }
+                }
+            }
+        }
+    }
+    if compress.is_some() {
+        let _ = fs::remove_file(&upload);
+    }
+}
+
+// the rows under the header in columns as wide as their widest cell, the numeric ones aligned right
+fn print_table(out: &mut dyn Write, header: &[&str], rows: &[Vec<String>], right: &[bool]) {
+    let mut widths = header.iter().map(|h| h.chars().count()).collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let header = header.iter().map(|h| h.to_string()).collect::<Vec<_>>();
+    for row in std::iter::once(&header).chain(rows) {
+        let cells = row.iter().enumerate().map(|(i, cell)| {
+            let pad = " ".repeat(widths[i] - cell.chars().count());
+            match right[i] {
+                true => pad + cell,
+                false => cell.clone() + &pad,
+            }
+        });
+        outln!(out, "{}", cells.collect::<Vec<_>>().join("  ").trim_end());
+    }
+}
+
+// the first characters of a blob id keep the listings narrow, --full-blob-id shows all of it
+fn blob_id_display(blob_id: &str, full: bool) -> String {
+    if full || blob_id.chars().count() <= BLOB_ID_PREFIX {
+        blob_id.to_owned()
+    } else {
+        format!("{}…", blob_id.chars().take(BLOB_ID_PREFIX).collect::<String>())
+    }
+}
+
+// a truncated or corrupted walrus read shows up as a size mismatch
+fn verify_size(filename: &String, size: u64) -> Result<(), anyhow::Error> {
+    let len = fs::metadata(filename)?.len();
+    if len != size {
+        anyhow::bail!("Downloaded {} is {} bytes but the object is {} bytes, the download may be truncated or corrupted", filename, len, size);
+    }
+    Ok(())
+}
+
+// download and check the blob, then undo the compression put applied to it
+fn download_blob(blob_id: &String, size: u64, dest_filename: &String, compression: Option<&str>) -> Result<(), anyhow::Error> {
+    let Some(compression) = compression else {
+        walrus::backend().download(blob_id, dest_filename)?;
+        return verify_size(dest_filename, size);
+    };
+    let blob_filename = format!("{}.suis3part", dest_filename);
+    let result = walrus::backend()
+        .download(blob_id, &blob_filename)
+        .and_then(|()| verify_size(&blob_filename, size))
+        .and_then(|()| utils::decompress_file(Path::new(&blob_filename), Path::new(dest_filename), compression));
+    let _ = fs::remove_file(&blob_filename);
+    result
+}
+
+// download_blob, telling which object expired when walrus no longer stores its blob. with retry_on_expiry
+// walrus is asked for the status of the blob first, one still stored is downloaded again
+fn download_object(bucket_name: &str, meta: &walrus::BlobMeta, uri: &str, dest_filename: &String, compression: Option<&str>,
+                   retry_on_expiry: bool) -> Result<(), anyhow::Error> {
+    let e = match download_blob(&meta.walrus_blob_id, meta.size, dest_filename, compression) {
+        Ok(()) => return Ok(()),
+        Err(e) if !walrus::is_blob_unavailable(&e) => return Err(e),
+        Err(e) => e,
+    };
+    log::info!("Walrus cannot read blob {}: {}", meta.walrus_blob_id, e);
+    if retry_on_expiry && walrus::backend().blob_status(&meta.walrus_blob_id).is_ok() {
+        log::info!("Blob {} is still stored, downloading it again", meta.walrus_blob_id);
+        return download_blob(&meta.walrus_blob_id, meta.size, dest_filename, compression);
+    }
+    let hint = match retry_on_expiry {
+        true => "put the file again to store a new blob; a blob can only be extended before its end epoch",
+        false => "rerun with --retry-on-expiry to ask walrus about the blob again",
+    };
+    Err(operations::SuiS3Error::WalrusFailure(format!(
+        "Object suis3://{}{} expired at epoch {}, walrus no longer serves its blob {}: {}",
+        bucket_name, uri, meta.walrus_epoch_till, meta.walrus_blob_id, hint
+    ))
+    .into())
+}
+
+// a versioned object is saved under the name of its key whatever the version
+async fn get_file(out: &mut dyn Write, uri: String, file: Option<String>, options: &GetOptions) {
+    let GetOptions { preserve_path, no_clobber, ref decompress, retry_on_expiry, version, .. } = *options;
+    let Some(caps) = parse_uri(&uri) else {
+        errln!("SUIS3 object format error.");
+        return;
+    };
+    let bucket_name = caps.bucket.as_str();
+    let obj_name = caps.object.as_str();
+
+    if obj_name.len() == 0 {
+        errln!("SUIS3 object format error.");
+    } else {
+        let mut dest_filename;
+        if preserve_path {
+            // the key is written under the folder, never above it
+            if obj_name.split('/').any(|part| part == "..") {
+                errln!("Unsafe key {} cannot be preserved as a path.", obj_name);
+                return;
+            }
+            let dir = file.unwrap_or(settings::get().config.download_dir);
+            dest_filename = Path::new(&dir).join(obj_name.trim_start_matches('/')).to_str().unwrap().to_owned();
+        } else if file.is_none() {
+            // the download_dir config, the current folder by default
+            let dir = settings::get().config.download_dir;
+            if let Err(e) = fs::create_dir_all(&dir) {
+                failln!(e);
+                return;
+            }
+            let filename = Path::new(obj_name).file_name().unwrap();
+            dest_filename = Path::new(&dir).join(filename).to_str().unwrap().to_owned();
+        } else {
+            dest_filename = file.unwrap();
+        }
+
+        // download into an existing local folder with the object file name
+        let dest_path = Path::new(&dest_filename);
+        if dest_path.is_dir() {
+            let filename = Path::new(obj_name).file_name().unwrap();
+            dest_filename = dest_path.join(filename).to_str().unwrap().to_owned();
+        }
+        if no_clobber && Path::new(&dest_filename).exists() {
+            errln!("Skipped: {} already exists and --no-clobber keeps it.", dest_filename);
+            return;
+        }
+        
+        let start = Instant::now();
+        match operations::resolve_version(bucket_name, obj_name, version).await {
+            Err(e) => failln!(e),
+            Ok((key, meta)) => {
+                log::info!("Reading the metadata took {:.2?}", start.elapsed());
+                let start = Instant::now();
+                let compression = decompress.as_deref().or(operations::tag_value(&meta.tags, operations::CONTENT_ENCODING_TAG));
+                let result = download_object(bucket_name, &meta, &key, &dest_filename, compression, retry_on_expiry);
+                log::info!("Walrus download of {} bytes took {:.2?}", meta.size, start.elapsed());
+                match result {
+                    Err(e) => failln!(e),
+                    Ok(()) => {
+                        statusln!(out, "Saved as: {}", dest_filename);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// repair: each expiring blob is downloaded and stored again once, then every object on it is pointed at the new blob
+async fn repair_bucket(out: &mut dyn Write, uri: String, within: u64, concurrency: usize, dry_run: bool) {
+    let Some(caps) = parse_uri(&uri).filter(|caps| caps.object.is_empty()) else {
+        errln!("SUIS3 bucket format error.");
+        return;
+    };
+    let bucket_name = caps.bucket.as_str();
+
+    if let Err(e) = walrus::ensure_walrus_available() {
+        failln!(e);
+        return;
+    }
+    if !dry_run && !preflight(true).await {
+        return;
+    }
+    let current = match walrus::backend().current_epoch() {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(epoch) => epoch,
+    };
+    let ret = match operations::get_bucket_objects(bucket_name.to_owned()).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(ret) => ret,
+    };
+
+    // objects copied with cp share a blob, it is stored again once for all of them
+    let mut blobs: Vec<(String, Vec<operations::BucketObjectsInfo>)> = Vec::new();
+    for obj in ret.objects.into_iter().filter(|obj| obj.walrus_epoch_till <= current + within) {
+        match blobs.iter_mut().find(|(blob_id, _)| *blob_id == obj.walrus_blob_id) {
+            Some((_, objects)) => objects.push(obj),
+            None => blobs.push((obj.walrus_blob_id.clone(), vec![obj])),
+        }
+    }
+    if dry_run {
+        for obj in blobs.iter().flat_map(|(_, objects)| objects) {
+            outln!(out, "Would repair: suis3://{}{} (blob {}, till epoch {})", bucket_name, obj.uri,
+                   blob_id_display(&obj.walrus_blob_id, false), obj.walrus_epoch_till);
+        }
+        statusln!(out, "Current epoch {}, {} blobs end by epoch {}", current, blobs.len(), current + within);
+        return;
+    }
+    statusln!(out, "Current epoch {}, storing again {} blobs ending by epoch {}", current, blobs.len(), current + within);
+
+    let stored = stream::iter(blobs.into_iter().enumerate().map(|(i, (blob_id, objects))| async move {
+        let result = tokio::task::spawn_blocking(move || -> Result<walrus::BlobMeta, anyhow::Error> {
+            let tmp = env::temp_dir().join(format!("suis3_repair_{}_{}", std::process::id(), i)).to_str().unwrap().to_owned();
+            let stored = walrus::backend().download(&blob_id, &tmp).and_then(|()| walrus::backend().upload_again(&tmp));
+            let _ = fs::remove_file(&tmp);
+            stored
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|r| r);
+        (objects, result)
+    }))
+    .buffer_unordered(concurrency.max(1))
+    .collect::<Vec<_>>()
+    .await;
+
+    // the metadata transactions run one at a time, they share the buckets root
+    let mut repaired = 0;
+    let mut failed = 0;
+    for (objects, result) in stored {
+        let blob = match result {
+            Ok(blob) if blob.walrus_epoch_till > objects[0].walrus_epoch_till => blob,
+            Ok(blob) => {
+                errln!("Failed: walrus kept blob {} till epoch {}, suis3://{}{} is not repaired", blob.walrus_blob_id, blob.walrus_epoch_till,
+                       bucket_name, objects[0].uri);
+                failed += objects.len();
+                continue;
+            }
+            Err(e) => {
+                errln!("Failed: suis3://{}{}: {}", bucket_name, objects[0].uri, e);
+                failed += objects.len();
+                continue;
+            }
+        };
+        for obj in objects {
+            let meta = walrus::BlobMeta {
+                walrus_blob_id: blob.walrus_blob_id.clone(),
+                walrus_epoch_till: blob.walrus_epoch_till,
+                ..obj.to_blob_meta()
+            };
+            match operations::create_object(bucket_name, &obj.uri, &meta).await {
+                Err(e) => {
+                    errln!("Failed: suis3://{}{}: {}", bucket_name, obj.uri, e);
+                    failed += 1;
+                }
+                Ok(digest) => {
+                    statusln!(out, "Repaired: suis3://{}{} till epoch {} (was {})", bucket_name, obj.uri, meta.walrus_epoch_till, obj.walrus_epoch_till);
+                    print_digest(out, &digest);
+                    repaired += 1;
+                }
+            }
+        }
+    }
+    statusln!(out, "Repaired {}, failed {}", repaired, failed);
+}
+
+// get -r notes each finished object in this file of the folder, one json line each, so a rerun skips it.
+// the blob id tells a changed object, the local size a truncated or edited file
+const GET_MANIFEST: &str = ".suis3-get";
+
+// uri -> (blob id, local size), the last line of an object wins
+fn read_get_manifest(dest_dir: &str) -> HashMap<String, (String, u64)> {
+    let Ok(content) = fs::read_to_string(Path::new(dest_dir).join(GET_MANIFEST)) else {
+        return HashMap::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| {
+            let v: serde_json::Value = serde_json::from_str(line).ok()?;
+            Some((v["uri"].as_str()?.to_owned(), (v["blob_id"].as_str()?.to_owned(), v["size"].as_u64()?)))
+        })
+        .collect()
+}
+
+fn note_downloaded(dest_dir: &Path, uri: &str, blob_id: &str, size: u64) -> Result<(), anyhow::Error> {
+    let line = serde_json::json!({ "uri": uri, "blob_id": blob_id, "size": size }).to_string() + "\n";
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(dest_dir.join(GET_MANIFEST))?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+async fn get_bucket(out: &mut dyn Write, uri: String, dest_dir: String, options: &GetOptions) {
+    let GetOptions { no_clobber, ref decompress, retry_on_expiry, concurrency, dry_run, force, .. } = *options;
+    let Some(caps) = parse_uri(&uri) else {
+        errln!("SUIS3 object format error.");
+        return;
+    };
+    let bucket_name = caps.bucket.as_str();
+
+    if !dry_run {
+        // reading from an aggregator needs no walrus cli
+        let walrus_cli = settings::get().read_aggregator.is_none();
+        if walrus_cli {
+            if let Err(e) = walrus::ensure_walrus_available() {
+                failln!(e);
+                return;
+            }
+        }
+        if !preflight(walrus_cli).await {
+            return;
+        }
+    }
+
+    let ret = match operations::get_bucket_objects(bucket_name.to_owned()).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(ret) => ret,
+    };
+
+    let manifest = if force { HashMap::new() } else { read_get_manifest(&dest_dir) };
+    let mut jobs = Vec::new();
+    let mut present = 0;
+    let mut kept = 0;
+    for obj in ret.objects {
+        // keys are written under the folder, never above it
+        if obj.uri.split('/').any(|part| part == "..") {
+            errln!("Skipped unsafe key: {}", obj.uri);
+            continue;
+        }
+        let dest = Path::new(&dest_dir).join(obj.uri.trim_start_matches('/'));
+        if let Ok(m) = fs::metadata(&dest) {
+            let done = match manifest.get(&obj.uri) {
+                Some((blob_id, size)) => *blob_id == obj.walrus_blob_id && m.len() == *size,
+                // downloaded before the manifest, or by hand
+                None => m.len() == obj.size,
+            };
+            if m.is_file() && done && !force {
+                present += 1;
+                continue;
+            }
+            if no_clobber {
+                statusln!(out, "Skipped (exists): {}", dest.display());
+                kept += 1;
+                continue;
+            }
+        }
+        jobs.push((obj, dest));
+    }
+    if dry_run {
+        for (obj, dest) in jobs.iter() {
+            outln!(out, "Would download: suis3://{}{} to {}", bucket_name, obj.uri, dest.display());
+        }
+        statusln!(out, "{} already present, would download {}", present, jobs.len());
+        return;
+    }
+    statusln!(out, "{} already present, downloading {}", present, jobs.len());
+
+    let dest_dir = Path::new(&dest_dir);
+    let results = stream::iter(jobs.into_iter().map(|(obj, dest)| async move {
+        let compression = decompress
+            .clone()
+            .or(operations::tag_value(&obj.tags, operations::CONTENT_ENCODING_TAG).map(|c| c.to_owned()));
+        let meta = obj.to_blob_meta();
+        let dest_filename = dest.to_str().unwrap().to_owned();
+        let (dest_dir, uri, bucket_name) = (dest_dir.to_path_buf(), obj.uri.clone(), bucket_name.to_owned());
+        let result = tokio::task::spawn_blocking(move || -> Result<(), anyhow::Error> {
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            download_object(&bucket_name, &meta, &uri, &dest_filename, compression.as_deref(), retry_on_expiry)?;
+            // a lost note only costs downloading the object again
+            let len = fs::metadata(&dest_filename)?.len();
+            if let Err(e) = note_downloaded(&dest_dir, &uri, &meta.walrus_blob_id, len) {
+                log::warn!("Cannot note {} in {}: {}", uri, GET_MANIFEST, e);
+            }
+            Ok(())
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|r| r);
+        (obj.uri, result)
+    }))
+    .buffer_unordered(concurrency.max(1))
+    .collect::<Vec<_>>()
+    .await;
+
+    let mut failed = 0;
+    for (uri, result) in results.iter() {
+        if let Err(e) = result {
+            errln!("Failed: suis3://{}{}: {}", bucket_name, uri, e);
+            failed += 1;
+        }
+    }
+    statusln!(out, "Downloaded {}, failed {}, already present {}", results.len() - failed, failed, present);
+    if kept > 0 {
+        statusln!(out, "Kept {} existing files which differ from the objects, see --no-clobber", kept);
+    }
+}
+
+// the contract drops the objects with the bucket, so a bucket with objects is only deleted with -r,
+// after its objects. a locked object stops it, like del
+async fn delete_bucket(out: &mut dyn Write, name: &str, recursive: bool) -> bool {
+    let list = match operations::get_bucket_objects(name.to_owned()).await {
+        Err(e) => {
+            errln!("Failed: suis3://{}: {}", name, e);
+            return false;
+        }
+        Ok(list) => list,
+    };
+    let count = list.objects.len();
+    if count > 0 && !recursive {
+        errln!("Failed: suis3://{} still has {} object(s), delete them first or use rb -r", name, count);
+        return false;
+    }
+    let locked = list.objects.iter().filter(|obj| operations::is_locked(&obj.tags)).count();
+    if locked > 0 {
+        errln!("Failed: suis3://{} has {} locked object(s), unlock them first", name, locked);
+        return false;
+    }
+    // a small bucket goes without asking, more objects than confirm_threshold need a yes
+    let threshold = settings::get().config.confirm_threshold;
+    if count as u64 > threshold && !confirm(&format!("Delete the {} objects of suis3://{} (more than confirm_threshold {})?", count, name, threshold)) {
+        statusln!(out, "Skipped: suis3://{}", name);
+        return false;
+    }
+
+    for (i, obj) in list.objects.iter().enumerate() {
+        match operations::delete_object(name.to_owned(), obj.uri.clone()).await {
+            Err(e) => {
+                errln!("Failed: suis3://{}{}: {}, the bucket is kept", name, obj.uri, e);
+                return false;
+            }
+            Ok(digest) => {
+                statusln!(out, "Deleted suis3://{}{} ({}/{})", name, obj.uri, i + 1, count);
+                print_digest(out, &digest);
+            }
+        }
+    }
+
+    match operations::delete_bucket(name.to_owned()).await {
+        Err(e) => {
+            errln!("Failed: suis3://{}: {}", name, e);
+            false
+        }
+        Ok(digest) => {
+            statusln!(out, "Deleted bucket: suis3://{}", name);
+            print_digest(out, &digest);
+            true
+        }
+    }
+}
+
+// the contract has no rename, so the objects are copied into a new bucket
+// and the old bucket is only deleted once every object made it over
+async fn rename_bucket(out: &mut dyn Write, old: String, new: String, batch_size: usize, dry_run: bool) {
+    let (Some(old), Some(new)) = (parse_uri(&old), parse_uri(&new)) else {
+        errln!("SUIS3 object format error.");
+        return;
+    };
+    if old.object.len() != 0 || new.object.len() != 0 {
+        errln!("SUIS3 object format error.");
+        return;
+    }
+    if old.bucket == new.bucket {
+        errln!("The new bucket name is the same as the old one.");
+        return;
+    }
+    if !dry_run && !preflight(false).await {
+        return;
+    }
+
+    match operations::bucket_exists(&new.bucket).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(true) => {
+            errln!("Bucket suis3://{} already exists.", new.bucket);
+            return;
+        }
+        Ok(false) => {}
+    }
+
+    let objects = match operations::get_bucket_objects(old.bucket.clone()).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(ret) => ret.objects,
+    };
+    let tags = match operations::list_bucket_tags(old.bucket.clone()).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(tags) => tags,
+    };
+
+    if dry_run {
+        outln!(out, "Would create bucket: suis3://{}", new.bucket);
+        if tags.len() > 0 {
+            outln!(out, "Would tag suis3://{}: {}", new.bucket, tags.join(" "));
+        }
+        for obj in objects.iter() {
+            outln!(out, "Would copy: suis3://{}{} to suis3://{}{}", old.bucket, obj.uri, new.bucket, obj.uri);
+        }
+        outln!(out, "Would delete bucket: suis3://{}", old.bucket);
+        return;
+    }
+
+    match operations::create_bucket(new.bucket.clone(), tags).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(digest) => {
+            statusln!(out, "Created bucket: suis3://{}", new.bucket);
+            print_digest(out, &digest);
+        }
+    }
+
+    let objects = objects.iter().map(|o| (o.uri.clone(), o.to_blob_meta())).collect::<Vec<_>>();
+    let mut copied = 0;
+    for batch in objects.chunks(batch_size.max(1)) {
+        match operations::create_objects(&new.bucket, batch).await {
+            Err(e) => {
+                errln!("Failed to copy the objects: {}", e);
+                errln!("Copied {} of {} objects. Kept suis3://{}, suis3://{} holds a partial copy.",
+                         copied, objects.len(), old.bucket, new.bucket);
+                return;
+            }
+            Ok(_) => copied += batch.len(),
+        }
+    }
+    statusln!(out, "Copied {} objects", copied);
+
+    match operations::delete_bucket(old.bucket.clone()).await {
+        Err(e) => errln!("Failed to delete suis3://{}: {}", old.bucket, e),
+        Ok(digest) => {
+            statusln!(out, "Renamed suis3://{} to suis3://{}", old.bucket, new.bucket);
+            print_digest(out, &digest);
+        }
+    }
+}
+
+async fn reprefix(out: &mut dyn Write, uri: String, from: String, to: String, batch_size: usize, dry_run: bool) {
+    let Some(caps) = parse_uri(&uri) else {
+        errln!("SUIS3 object format error.");
+        return;
+    };
+    if caps.object.len() != 0 {
+        errln!("SUIS3 object format error.");
+        return;
+    }
+    let bucket = caps.bucket;
+    // object uris are kept with a leading slash
+    let from = "/".to_owned() + from.trim_start_matches('/');
+    let to = "/".to_owned() + to.trim_start_matches('/');
+    if from == to {
+        errln!("The new prefix is the same as the old one.");
+        return;
+    }
+    if !dry_run && !preflight(false).await {
+        return;
+    }
+
+    let objects = match operations::get_bucket_objects(bucket.clone()).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(ret) => ret.objects,
+    };
+    let moves = objects
+        .iter()
+        .filter(|o| o.uri.starts_with(&from))
+        .map(|o| (o.uri.clone(), to.clone() + &o.uri[from.len()..], o.to_blob_meta()))
+        .collect::<Vec<_>>();
+    if moves.is_empty() {
+        statusln!(out, "No object of suis3://{} starts with {}", bucket, from);
+        return;
+    }
+
+    // nothing is overwritten, not even an object moved away later in the same run
+    let taken = moves
+        .iter()
+        .filter(|(_, new, _)| objects.iter().any(|o| o.uri == *new))
+        .map(|(_, new, _)| new.as_str())
+        .collect::<Vec<_>>();
+    if taken.len() > 0 {
+        for new in taken.iter() {
+            errln!("suis3://{}{} already exists, reprefix does not overwrite objects.", bucket, new);
+        }
+        return;
+    }
+    // nor moves a locked object, the whole run is refused like above
+    let locked = moves
+        .iter()
+        .filter_map(|(old, _, meta)| operations::ensure_unlocked(&bucket, old, &meta.tags, false).err())
+        .collect::<Vec<_>>();
+    if locked.len() > 0 {
+        for e in locked.iter() {
+            errln!("{}", e);
+        }
+        return;
+    }
+
+    if dry_run {
+        for (old, new, _) in moves.iter() {
+            outln!(out, "Would move: suis3://{}{} to suis3://{}{}", bucket, old, bucket, new);
+        }
+        return;
+    }
+
+    let mut moved = 0;
+    for batch in moves.chunks(batch_size.max(1)) {
+        match operations::move_objects(&bucket, batch).await {
+            Err(e) => {
+                errln!("Failed to move the objects: {}", e);
+                errln!("Moved {} of {} objects, the failed batch was rolled back and the rest is left under {}.",
+                         moved, moves.len(), from);
+                return;
+            }
+            Ok(digest) => {
+                for (old, new, _) in batch.iter() {
+                    statusln!(out, "Moved suis3://{}{} to suis3://{}{}", bucket, old, bucket, new);
+                }
+                print_digest(out, &digest);
+                moved += batch.len();
+            }
+        }
+    }
+    statusln!(out, "Moved {} objects", moved);
+}
+
+async fn copy(out: &mut dyn Write, src: String, dst: String, preserve: bool) {
+    match (parse_uri(&src).is_some(), parse_uri(&dst).is_some()) {
+        (false, true) => put_file(out, src, dst, PutOptions::default()).await,
+        (true, false) => get_file(out, src, Some(dst), &GetOptions::default()).await,
+        (true, true) => copy_object(out, src, dst, preserve, false).await,
+        (false, false) => errln!("One side of cp must be a SUIS3 object (suis3://<bucket>/<object>)."),
+    }
+}
+
+// a new object pointing at the blob of the source, so the blob id, size and till epoch carry over.
+// the write time is set on chain, with preserve the tags are copied and the source time kept as a tag.
+// a move within a bucket is one transaction, across buckets the source is deleted once the copy exists
+async fn copy_object(out: &mut dyn Write, src: String, dst: String, preserve: bool, remove_source: bool) {
+    let (Some(src_caps), Some(dst_caps)) = (parse_uri(&src), parse_uri(&dst)) else {
+        errln!("SUIS3 object format error.");
+        return;
+    };
+    if src_caps.object.len() == 0 {
+        errln!("SUIS3 object format error.");
+        return;
+    }
+    // into the "folder" with the source name
+    let mut dst_obj = dst_caps.object.clone();
+    if dst_obj.len() == 0 || dst_obj.ends_with('/') {
+        // a key like / or /docs/.. has no name to give the copy
+        let Some(filename) = Path::new(&src_caps.object).file_name().and_then(|name| name.to_str()) else {
+            errln!("suis3://{}{} is not an object, name the destination object.", src_caps.bucket, src_caps.object);
+            return;
+        };
+        dst_obj = dst_obj.trim_end_matches('/').to_owned() + "/" + filename;
+    }
+    let (src_bucket, src_obj, dst_bucket) = (src_caps.bucket, src_caps.object, dst_caps.bucket);
+    if src_bucket == dst_bucket && src_obj == dst_obj {
+        errln!("suis3://{}{} is both the source and the destination", src_bucket, src_obj);
+        return;
+    }
+
+    let meta = match operations::get_object(src_bucket.clone(), src_obj.clone()).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(meta) => meta,
+    };
+    if remove_source {
+        if let Err(e) = operations::ensure_unlocked(&src_bucket, &src_obj, &meta.tags, false) {
+            errln!("{}", e);
+            return;
+        }
+    }
+    // a missing destination fails the lookup, which is fine to write
+    if let Ok(dst_meta) = operations::get_object(dst_bucket.clone(), dst_obj.clone()).await {
+        if let Err(e) = operations::ensure_unlocked(&dst_bucket, &dst_obj, &dst_meta.tags, false) {
+            errln!("{}", e);
+            return;
+        }
+    }
+
+    let key = |tag: &String| tag.split('=').next().unwrap().trim().to_owned();
+    let tags = if preserve {
+        // a copy of a copy keeps the first write time
+        let mut tags = meta.tags.iter().filter(|tag| key(tag) != operations::LOCKED_TAG).cloned().collect::<Vec<_>>();
+        if operations::tag_value(&tags, operations::ORIGINAL_WRITE_TS_TAG).is_none() {
+            tags.push(format!("{}={}", operations::ORIGINAL_WRITE_TS_TAG, meta.last_write_ts));
+        }
+        tags
+    } else {
+        // the tags get and cat need to read the blob
+        meta.tags
+            .iter()
+            .filter(|tag| [operations::CONTENT_TYPE_TAG, operations::CONTENT_ENCODING_TAG].contains(&key(tag).as_str()))
+            .cloned()
+            .collect()
+    };
+    let copied = walrus::BlobMeta { tags, ..meta };
+
+    let result = if remove_source && src_bucket == dst_bucket {
+        operations::move_objects(&src_bucket, &[(src_obj.clone(), dst_obj.clone(), copied)]).await
+    } else {
+        operations::create_object(&dst_bucket, &dst_obj, &copied).await
+    };
+    match result {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(digest) => {
+            statusln!(out, "{} suis3://{}{} to suis3://{}{}", if remove_source { "Moved" } else { "Copied" },
+                      src_bucket, src_obj, dst_bucket, dst_obj);
+            print_digest(out, &digest);
+        }
+    }
+    if remove_source && src_bucket != dst_bucket {
+        match operations::delete_object(src_bucket.clone(), src_obj.clone()).await {
+            Err(e) => errln!("Copied to suis3://{}{} but could not delete the source: {}", dst_bucket, dst_obj, e),
+            Ok(digest) => print_digest(out, &digest),
+        }
+    }
+}
+
+// the lock stays with each object, it is neither copied nor dropped
+async fn copy_tags(out: &mut dyn Write, src: String, dst: String, replace: bool) {
+    let (Some(src_caps), Some(dst_caps)) = (parse_uri(&src), parse_uri(&dst)) else {
+        errln!("SUIS3 object format error.");
+        return;
+    };
+    if src_caps.object.len() == 0 || dst_caps.object.len() == 0 {
+        errln!("SUIS3 object format error.");
+        return;
+    }
+    let is_lock = |tag: &String| tag.split('=').next().unwrap().trim() == operations::LOCKED_TAG;
+
+    let src_tags = match operations::list_object_tags(src_caps.bucket.clone(), src_caps.object.clone()).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(tags) => tags.into_iter().filter(|tag| !is_lock(tag)).collect::<Vec<_>>(),
+    };
+    let dst_tags = match operations::list_object_tags(dst_caps.bucket.clone(), dst_caps.object.clone()).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(tags) => tags,
+    };
+    let count = src_tags.len();
+    let tags = if replace {
+        let mut tags = dst_tags.iter().filter(|tag| is_lock(tag)).cloned().collect::<Vec<_>>();
+        tags.extend(src_tags);
+        tags
+    } else {
+        operations::merge_tags(&dst_tags, &src_tags)
+    };
+    if tags == dst_tags {
+        statusln!(out, "{} already has the tags of {}", dst, src);
+        return;
+    }
+
+    match operations::tag_object(dst_caps.bucket.clone(), dst_caps.object.clone(), tags).await {
+        Err(e) => failln!(e),
+        Ok(digest) => {
+            statusln!(out, "Copied {} tag(s) of {} to {}", count, src, dst);
+            print_digest(out, &digest);
+        }
+    }
+}
+
+// tag add of several uris or a pattern: the objects are checked against the listing of the bucket,
+// then tagged a batch per transaction. a failed batch is retried object by object to tell the failing ones.
+// each object gets the tags merged into its own from the listing, the contract replaces the whole tag vector
+async fn tag_objects(out: &mut dyn Write, uris: &[String], tags: Vec<String>, batch_size: usize) {
+    let targets = uris.iter().filter_map(|uri| parse_uri(uri)).collect::<Vec<_>>();
+    if targets.len() != uris.len() || targets.iter().any(|t| t.object.len() == 0) {
+        errln!("SUIS3 object format error, tag add of several uris tags objects only.");
+        return;
+    }
+    let bucket_name = targets[0].bucket.clone();
+    if targets.iter().any(|t| t.bucket != bucket_name) {
+        errln!("tag add tags the objects of one bucket at a time.");
+        return;
+    }
+    let list = match operations::get_bucket_objects(bucket_name.clone()).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(list) => list,
+    };
+
+    let (mut done, mut failed) = (0, 0);
+    let mut objects: Vec<(String, Vec<String>)> = Vec::new();
+    for target in targets.iter() {
+        let found = list
+            .objects
+            .iter()
+            .filter(|o| match utils::is_glob(&target.object) {
+                true => utils::glob_match(&target.object, &o.uri),
+                false => o.uri == target.object,
+            })
+            .collect::<Vec<_>>();
+        if found.is_empty() {
+            errln!("Failed: {}", operations::SuiS3Error::ObjectNotFound(bucket_name.clone(), target.object.clone()));
+            failed += 1;
+        }
+        for obj in found {
+            if !objects.iter().any(|(name, _)| *name == obj.uri) {
+                objects.push((obj.uri.clone(), operations::add_tags(&obj.tags, &tags)));
+            }
+        }
+    }
+
+    for batch in objects.chunks(batch_size.max(1)) {
+        match operations::tag_objects(&bucket_name, batch).await {
+            Ok(digest) => {
+                for (name, _) in batch.iter() {
+                    statusln!(out, "Tagged suis3://{}{}", bucket_name, name);
+                }
+                print_digest(out, &digest);
+                done += batch.len();
+            }
+            Err(e) => {
+                log::warn!("{}, tagging its objects one by one", e);
+                for (name, tags) in batch.iter() {
+                    match operations::tag_object(bucket_name.clone(), name.clone(), tags.clone()).await {
+                        Err(e) => {
+                            errln!("Failed: suis3://{}{}: {}", bucket_name, name, e);
+                            failed += 1;
+                        }
+                        Ok(digest) => {
+                            statusln!(out, "Tagged suis3://{}{}", bucket_name, name);
+                            print_digest(out, &digest);
+                            done += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    statusln!(out, "Tagged {}, failed {}", done, failed);
+}
+
+// set or clear the locked tag, keeping the other tags of the object
+async fn set_lock(out: &mut dyn Write, uri: String, lock: bool) {
+    let Some(caps) = parse_uri(&uri) else {
+        errln!("SUIS3 object format error.");
+        return;
+    };
+    let bucket_name = caps.bucket.as_str();
+    let obj_name = caps.object.as_str();
+    if obj_name.len() == 0 {
+        errln!("SUIS3 object format error.");
+        return;
+    }
 
-    #[command(
-        name = "ll",
-        about = r#"list all objects detail of the bucket
-    ll s3://<bucket>"#
-    )]
-    Detail { uri: Option<String> },
+    let meta = match operations::get_object(bucket_name.to_owned(), obj_name.to_owned()).await {
+        Err(e) => {
+            failln!(e);
+            return;
+        }
+        Ok(meta) => meta,
+    };
+    if operations::is_locked(&meta.tags) == lock {
+        statusln!(out, "suis3://{}{} is already {}", bucket_name, obj_name, if lock { "locked" } else { "unlocked" });
+        return;
+    }
+    let mut tags = meta
+        .tags
+        .into_iter()
+        .filter(|tag| tag.split('=').next().unwrap().trim() != operations::LOCKED_TAG)
+        .collect::<Vec<_>>();
+    if lock {
+        tags.push(format!("{}=true", operations::LOCKED_TAG));
+    }
 
-    #[command(
-        name = "mb",
-        about = r#"create bucket
-    mb s3://<bucket>"#
-    )]
-    CreateBucket { bucket: String },
+    match operations::tag_object(bucket_name.to_owned(), obj_name.to_owned(), tags).await {
+        Err(e) => failln!(e),
+        Ok(digest) => {
+            statusln!(out, "{} suis3://{}{}", if lock { "Locked" } else { "Unlocked" }, bucket_name, obj_name);
+            print_digest(out, &digest);
+        }
+    }
+}
 
-    #[command(
-        name = "rb",
-        about = r#"delete bucket
-    rb s3://<bucket>"#
-    )]
-    DeleteBucket { bucket: String },
+// the key=value lines of a tag file, the first malformed line fails the whole file
+fn read_tags_file(file: &str) -> Result<Vec<String>, anyhow::Error> {
+    let content = fs::read_to_string(file).map_err(|e| anyhow::anyhow!("Cannot read {}: {}", file, e))?;
+    let mut tags = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.len() == 0 || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, _)) if key.trim().len() > 0 => tags.push(line.to_owned()),
+            _ => anyhow::bail!("Failed line {} of {}: {} is not <key>=<value>", i + 1, file, line),
+        }
+    }
+    Ok(tags)
+}
 
-    #[command(about = r#"upload the file with specify object name
-    put <file> s3://<bucket>/<object>
-upload the file as the same file name
-    put <file> s3://<bucket>"#)]
-    Put { file: String, uri: String },
+// one line of the doctor report, with the hint under a failed check
+fn report(out: &mut dyn Write, name: &str, result: Result<String, (String, &str)>) -> bool {
+    match result {
+        Ok(detail) => {
+            outln!(out, "PASS  {:<14}{}", name, detail);
+            true
+        }
+        Err((error, hint)) => {
+            outln!(out, "FAIL  {:<14}{}", name, error);
+            outln!(out, "      {:<14}{}", "", hint);
+            fail_with(1);
+            false
+        }
+    }
+}
 
-    #[command(about = r#"download the object
-    get s3://<bucket>/<object> <file>
-download the object to current folder
-    get s3://<bucket>/<object>"#)]
-    Get { uri: String, file: Option<String> },
+// the object exists on the network, with its type
+async fn check_object(client: &sui_sdk::SuiClient, id: &str) -> Result<String, String> {
+    let object_id: ObjectID = id.parse().map_err(|e| format!("{} is not an object id: {}", id, e))?;
+    let response = client
+        .read_api()
+        .get_object_with_options(object_id, SuiObjectDataOptions::new().with_type())
+        .await
+        .map_err(|e| e.to_string())?;
+    match response.data {
+        Some(data) => Ok(format!("{} ({})", id, data.type_.map(|t| t.to_string()).unwrap_or_default())),
+        None => Err(format!("{} is not found on the network", id)),
+    }
+}
 
-    #[command(about = r#"display the object content
-    cat s3://<bucket>/<object>"#)]
-    Cat { uri: String },
+async fn doctor(out: &mut dyn Write) {
+    let config = settings::get().config;
 
-    #[command(about = r#"delete the object
-    del s3://<bucket>/<object>"#)]
-    Del { uri: String },
+    let walrus = walrus::locate_walrus_bin(&config.walrus_bin)
+        .and_then(|path| Ok(format!("{}, {}", path.display(), walrus::probe_walrus_version()?)))
+        .map_err(|e| (e.to_string(), "put, get and cat need the walrus cli, the listings work without it"));
+    if report(out, "walrus", walrus) {
+        let version = walrus::detected_walrus_version().unwrap_or_default();
+        let compatible = walrus::check_walrus_compatible(&version)
+            .map(|()| version)
+            .map_err(|e| (e.to_string(), "the blob ids are read from the output of walrus store"));
+        report(out, "walrus version", compatible);
+    }
 
-    #[command(about = r#"delete the object
-    rm s3://<bucket>/<object>"#)]
-    Rm { uri: String },
+    let address = utils::retrieve_wallet().and_then(|mut wallet| {
+        let count = wallet.get_addresses().len();
+        Ok((utils::active_address(&mut wallet)?, count))
+    });
+    let address = match address {
+        Ok((address, count)) => {
+            report(out, "wallet", Ok(format!("{}, {} addresses in {}", address, count, utils::wallet_dir().unwrap().display())));
+            Some(address)
+        }
+        Err(e) => {
+            report(out, "wallet", Err((e.to_string(), "check the client config and keystore in the sui config dir, or the --profile")));
+            None
+        }
+    };
 
-    #[command(about = r#"tag operations
-list tags of the bucket or the object
-    tag ls/list s3://<bucket>[/<object>]
-add tags to the object
-    tag add/put s3://<bucket>/<object>  <key>=<value> ...
-remove tags from the object
-    tag del/rm s3://<bucket>/<object>"#)]
-    Tag {
-        #[arg(value_enum)]
-        action: TagAction,
-        uri: String,
-        tags: Vec<String>,
-    },
+    let client = match utils::sui_client().await {
+        Ok(client) => client,
+        Err(e) => {
+            report(out, "rpc", Err((format!("{}: {}", config.rpc_url, e), "check the network connection, or set rpc_url")));
+            return;
+        }
+    };
+    let rpc = client
+        .read_api()
+        .get_chain_identifier()
+        .await
+        .map(|chain| format!("{} (chain {}, {})", config.rpc_url, chain, config.network))
+        .map_err(|e| (format!("{}: {}", config.rpc_url, e), "check the network connection, or set rpc_url"));
+    if !report(out, "rpc", rpc) {
+        return;
+    }
 
-    #[command(name = "quit/exit", about = "quit the programe")]
-    Quit,
-    // #[command(name = "help", about = "show s3 command usage")]
-    // Help,
+    if let Some(address) = address {
+        let funds = match utils::fetch_coin(&client, &address).await {
+            Ok(Some(coin)) => Ok(format!("coin {} with {} MIST", coin.coin_object_id, coin.balance)),
+            Ok(None) => Err((
+                format!("no coin of {} holds the 5000000 MIST needed for gas", address),
+                "send SUI to the address, or `sui client faucet` on testnet and devnet",
+            )),
+            Err(e) => Err((e.to_string(), "check the network connection, or set rpc_url")),
+        };
+        report(out, "funds", funds);
+    }
+
+    let package = check_object(&client, &config.package_id)
+        .await
+        .map_err(|e| (e, "the package is deployed per network, set package_id for this network"));
+    report(out, "package", package);
+    let root = check_object(&client, &config.buckets_root)
+        .await
+        .map_err(|e| (e, "set buckets_root to the buckets root of the package"));
+    report(out, "buckets root", root);
 }
 
-#[derive(ValueEnum, PartialEq, Debug, Clone)]
-pub enum TagAction {
-    List,
-    Ls,
-    Add,
-    Put,
-    Del,
-    Rm,
+// run the command, the exit code of its first error or 0
+pub async fn do_command(command: Option<SuiS3Cmd>, out: &mut dyn Write) -> i32 {
+    EXIT_CODE.store(0, Ordering::Relaxed);
+    JSON_ERRORS.store(matches!(command, Some(SuiS3Cmd::Detail { json: true, .. })), Ordering::Relaxed);
+    run_command(command, out).await;
+    EXIT_CODE.load(Ordering::Relaxed)
 }
 
-pub async fn do_command(command: Option<SuiS3Cmd>) {
-    // println!("===== do command: {:?} =====", command);
+async fn run_command(command: Option<SuiS3Cmd>, out: &mut dyn Write) {
+    // outln!(out, "===== do command: {:?} =====", command);
+    operations::clear_object_cache();
     match command {
-        Some(SuiS3Cmd::ListAll) => {
-            match operations::list_buckets().await {
-                Err(e) => println!("{}", e),
-                Ok(v) => {
-                    println!("TIME\t\t\t\tBUCKET NAME");
-                    for bi in v.iter() {
-                        let timestamp = NaiveDateTime::from_timestamp((bi.create_ts/1000) as i64, 0);
-                        let date_time: DateTime<Local> = Local.from_local_datetime(&timestamp).unwrap();
-                        println!("{}\t{}", date_time, bi.name);
-                    }
-                }
+        Some(SuiS3Cmd::ListAll { porcelain, stats, count }) => {
+            if count {
+                print_bucket_count(out).await;
+            } else {
+                print_buckets(out, true, porcelain, stats).await;
             }
         },
-        Some(SuiS3Cmd::CreateBucket { bucket }) => {
-            let re = Regex::new(SUIS3_REGEXP).unwrap();
-            let caps = re.captures(&bucket);
-            if caps.is_none() {
-                println!("SUIS3 object format error.");
-                return;
-            }
-            let caps = caps.unwrap();
-            let name = &caps["bucket"];
+        Some(SuiS3Cmd::CreateBucket { buckets, tags }) => {
+            let (mut done, mut failed) = (0, 0);
+            for bucket in buckets.iter() {
+                // the suggested name is taken once confirmed, --yes takes it without asking.
+                // with no one to ask it is only printed
+                let name = match invalid_bucket_name(bucket) {
+                    None => match parse_uri(bucket) {
+                        Some(caps) => caps.bucket,
+                        None => {
+                            errln!("Failed: {}: SUIS3 object format error.", bucket);
+                            failed += 1;
+                            continue;
+                        }
+                    },
+                    Some((typed, suggestion)) => {
+                        let ask = settings::get().interactive || settings::get().yes;
+                        match suggestion {
+                            Some(suggestion) if ask && confirm(&bucket_name_error(&typed, &Some(suggestion.clone()))) => suggestion,
+                            suggestion => {
+                                // the suggestion was in the question already
+                                let suggestion = if ask { None } else { suggestion };
+                                errln!("Failed: {}", bucket_name_error(&typed, &suggestion));
+                                failed += 1;
+                                continue;
+                            }
+                        }
+                    }
+                };
+                let name = name.as_str();
 
-            match operations::create_bucket(name.to_owned()).await {
-                Err(e) => println!("{}", e),
-                Ok(()) => {}
+                match operations::create_bucket(name.to_owned(), tags.clone()).await {
+                    Err(e) => {
+                        errln!("Failed: suis3://{}: {}", name, e);
+                        failed += 1;
+                    }
+                    Ok(digest) => {
+                        statusln!(out, "Created bucket: suis3://{}", name);
+                        print_digest(out, &digest);
+                        done += 1;
+                    }
+                }
+            }
+            if buckets.len() > 1 {
+                statusln!(out, "Created {}, failed {}", done, failed);
             }
         }
-        Some(SuiS3Cmd::DeleteBucket { bucket }) => {
-            let re = Regex::new(SUIS3_REGEXP).unwrap();
-            let caps = re.captures(&bucket);
-            if caps.is_none() {
-                println!("SUIS3 object format error.");
+        Some(SuiS3Cmd::DeleteBucket { buckets, recursive }) => {
+            if recursive && !preflight(false).await {
                 return;
             }
-            let caps = caps.unwrap();
-            let name = &caps["bucket"];
-
-            match operations::delete_bucket(name.to_owned()).await {
-                Err(e) => println!("{}", e),
-                Ok(()) => {}
+            let (mut done, mut failed) = (0, 0);
+            for bucket in buckets.iter() {
+                let Some(caps) = parse_uri(bucket) else {
+                    errln!("Failed: {}: SUIS3 object format error.", bucket);
+                    failed += 1;
+                    continue;
+                };
+                match delete_bucket(out, caps.bucket.as_str(), recursive).await {
+                    true => done += 1,
+                    false => failed += 1,
+                }
             }
+            if buckets.len() > 1 {
+                statusln!(out, "Deleted {}, failed {}", done, failed);
+            }
+        }
+        Some(SuiS3Cmd::RenameBucket { old, new, batch_size, dry_run }) => {
+            rename_bucket(out, old, new, batch_size, dry_run).await;
+        }
+        Some(SuiS3Cmd::Reprefix { uri, from, to, batch_size, dry_run }) => {
+            reprefix(out, uri, from, to, batch_size, dry_run).await;
         }
         Some(SuiS3Cmd::Tag {
             action: TagAction::Add,
             uri,
-            tags,
+            mut tags,
+            from_file,
+            batch_size,
+            ..
         }) | Some(SuiS3Cmd::Tag {
             action: TagAction::Put,
             uri,
-            tags,
+            mut tags,
+            from_file,
+            batch_size,
+            ..
         }) => {
-            let re = Regex::new(SUIS3_REGEXP).unwrap();
-            let caps = re.captures(&uri);
-            if caps.is_none() {
-                println!("SUIS3 object format error.");
+            let Some(caps) = parse_uri(&uri) else {
+                errln!("SUIS3 object format error.");
+                return;
+            };
+            // more uris may follow the first one, before the tags
+            let more = tags.iter().take_while(|t| parse_uri(t).is_some()).count();
+            let uris = std::iter::once(uri.clone()).chain(tags.drain(..more)).collect::<Vec<_>>();
+            if let Some(file) = from_file {
+                match read_tags_file(&file) {
+                    Err(e) => {
+                        failln!(e);
+                        return;
+                    }
+                    Ok(file_tags) => tags.extend(file_tags),
+                }
+            }
+            if tags.len() == 0 {
+                errln!("No tags given, e.g. tag add suis3://<bucket>/<object> <key>=<value>");
+                return;
+            }
+            if let Some(tag) = tags.iter().find(|tag| operations::PROTECTED_TAGS.contains(&operations::tag_key(tag))) {
+                errln!("The {} tag is kept by suis3, set by lock, unlock or put, tag add cannot change it", operations::tag_key(tag));
                 return;
             }
-            let caps = caps.unwrap();        
-            let bucket_name = &caps["bucket"];
-            let obj_name = &caps["object"];
+            let bucket_name = caps.bucket.as_str();
+            let obj_name = caps.object.as_str();
 
-            if obj_name.len() == 0 {
+            if uris.len() > 1 || utils::is_glob(obj_name) {
+                tag_objects(out, &uris, tags, batch_size).await;
+            } else if obj_name.len() == 0 {
                 // tag bucket 
                 match operations::tag_bucket(bucket_name.to_owned(), tags).await {
-                    Err(e) => println!("{}", e),
-                    Ok(()) => {}
+                    Err(e) => failln!(e),
+                    Ok(digest) => print_digest(out, &digest),
                 }    
             } else {
-                match operations::tag_object(bucket_name.to_owned(), obj_name.to_owned(), tags).await {
-                    Err(e) => println!("{}", e),
-                    Ok(()) => {}
+                // the contract replaces all the tags of the object, the ones not given are carried over
+                let current = match operations::list_object_tags(bucket_name.to_owned(), obj_name.to_owned()).await {
+                    Err(e) => {
+                        failln!(e);
+                        return;
+                    }
+                    Ok(current) => current,
+                };
+                match operations::tag_object(bucket_name.to_owned(), obj_name.to_owned(), operations::add_tags(&current, &tags)).await {
+                    Err(e) => failln!(e),
+                    Ok(digest) => print_digest(out, &digest),
                 }    
             }
         }
@@ -194,32 +2541,29 @@ pub async fn do_command(command: Option<SuiS3Cmd>) {
             uri,
             ..
         }) => {
-            let re = Regex::new(SUIS3_REGEXP).unwrap();
-            let caps = re.captures(&uri);
-            if caps.is_none() {
-                println!("SUIS3 object format error.");
+            let Some(caps) = parse_uri(&uri) else {
+                errln!("SUIS3 object format error.");
                 return;
-            }
-            let caps = caps.unwrap();        
-            let bucket_name = &caps["bucket"];
-            let obj_name = &caps["object"];
+            };
+            let bucket_name = caps.bucket.as_str();
+            let obj_name = caps.object.as_str();
 
             if obj_name.len() == 0 {
                 // list bucket tag 
                 match operations::list_bucket_tags(bucket_name.to_owned()).await {
-                    Err(e) => println!("{}", e),
+                    Err(e) => failln!(e),
                     Ok(v) => {
                         for s in v.iter() {
-                            println!("{}", s);
+                            outln!(out, "{}", s);
                         }    
                     }
                 }    
             } else {
                 match operations::list_object_tags(bucket_name.to_owned(), obj_name.to_owned()).await {
-                    Err(e) => println!("{}", e),
+                    Err(e) => failln!(e),
                     Ok(v) => {
                         for s in v.iter() {
-                            println!("{}", s);
+                            outln!(out, "{}", s);
                         }    
                     }
                 }                 
@@ -229,244 +2573,702 @@ pub async fn do_command(command: Option<SuiS3Cmd>) {
         Some(SuiS3Cmd::Tag {
             action: TagAction::Del,
             uri,
+            force_unlock,
             ..
         }) | Some(SuiS3Cmd::Tag {
             action: TagAction::Rm,
             uri,
+            force_unlock,
             ..
         }) => {
-            let re = Regex::new(SUIS3_REGEXP).unwrap();
-            let caps = re.captures(&uri);
-            if caps.is_none() {
-                println!("SUIS3 object format error.");
+            let Some(caps) = parse_uri(&uri) else {
+                errln!("SUIS3 object format error.");
                 return;
-            }
-            let caps = caps.unwrap();        
-            let bucket_name = &caps["bucket"];
-            let obj_name = &caps["object"];
+            };
+            let bucket_name = caps.bucket.as_str();
+            let obj_name = caps.object.as_str();
 
             if obj_name.len() == 0 {
                 // delete bucket tag                
                 match operations::delete_bucket_tags(bucket_name.to_owned()).await {
-                    Err(e) => println!("{}", e),
-                    Ok(()) => {}
+                    Err(e) => failln!(e),
+                    Ok(digest) => print_digest(out, &digest),
+                }
+            } else {
+                // the contract would drop all the tags of the object, the protected ones are set as its only tags instead
+                let current = match operations::list_object_tags(bucket_name.to_owned(), obj_name.to_owned()).await {
+                    Err(e) => {
+                        failln!(e);
+                        return;
+                    }
+                    Ok(current) => current,
+                };
+                let kept = operations::protected_tags(&current)
+                    .into_iter()
+                    .filter(|tag| !(force_unlock && operations::tag_key(tag) == operations::LOCKED_TAG))
+                    .collect::<Vec<_>>();
+                let result = match kept.is_empty() {
+                    true => operations::delete_object_tags(bucket_name.to_owned(), obj_name.to_owned()).await,
+                    false => operations::tag_object(bucket_name.to_owned(), obj_name.to_owned(), kept).await,
+                };
+                match result {
+                    Err(e) => failln!(e),
+                    Ok(digest) => print_digest(out, &digest),
                 }
+            }
+        }
+
+        Some(SuiS3Cmd::Put { uri, file, create_bucket, content_type, full_blob_id, tags, no_inherit, compress, dedup, recursive, overlap, name, versioned,
+                             force_unlock }) => {
+            if let Some((typed, suggestion)) = invalid_bucket_name(&uri) {
+                errln!("{}", bucket_name_error(&typed, &suggestion));
+                return;
+            }
+            let uri = match name {
+                None => uri,
+                Some(name) => {
+                    let Some(caps) = parse_uri(&uri) else {
+                        errln!("SUIS3 object format error.");
+                        return;
+                    };
+                    // a folder of the uri is kept, an object name would be a second one
+                    if caps.object.len() != 0 && !caps.object.ends_with('/') {
+                        errln!("The object name is given twice, as {} in the uri and as {} with --name", caps.object, name);
+                        return;
+                    }
+                    let folder = if caps.object.len() == 0 { "/".to_owned() } else { caps.object };
+                    format!("suis3://{}{}{}", caps.bucket, folder, name.trim_start_matches('/'))
+                }
+            };
+            let options = PutOptions { create_bucket, content_type, full_blob_id, tags, no_inherit, compress, dedup, versioned, force_unlock, overlap };
+            if recursive {
+                put_folder(out, file, uri, options).await;
+            } else {
+                put_file(out, file, uri, options).await;
+            }
+        }
+
+        Some(SuiS3Cmd::Get { uri, file, recursive, concurrency, dry_run, preserve_path, no_clobber, force, decompress, retry_on_expiry, version }) => {
+            let options = GetOptions { preserve_path, no_clobber, decompress, retry_on_expiry, version, concurrency, dry_run, force };
+            if recursive {
+                get_bucket(out, uri, file.unwrap_or(settings::get().config.download_dir), &options).await;
             } else {
-                match operations::delete_object_tags(bucket_name.to_owned(), obj_name.to_owned()).await {
-                    Err(e) => println!("{}", e),
-                    Ok(()) => {}
+                get_file(out, uri, file, &options).await;
+            }
+        }
+
+        Some(SuiS3Cmd::History { uri, limit, scan }) => {
+            let Some(caps) = parse_uri(&uri) else {
+                errln!("SUIS3 object format error.");
+                return;
+            };
+            let bucket_name = caps.bucket.as_str();
+
+            match operations::get_bucket_history(bucket_name.to_owned(), limit, scan).await {
+                Err(e) => failln!(e),
+                Ok(history) => {
+                    outln!(out, "TIME\t\t\t\tOPERATION\t\tOBJECT\t\t\tTRANSACTION");
+                    for entry in history.entries.iter() {
+                        let time = match entry.timestamp_ms {
+                            Some(ts) => utils::display_time(ts),
+                            None => "-".to_owned(),
+                        };
+                        let object = entry.object.clone().unwrap_or("-".to_owned());
+                        outln!(out, "{}\t{}\t\t{}\t\t\t{}", time, entry.operation, object, entry.digest);
+                    }
+                    if history.stopped_early {
+                        statusln!(out, "Stopped after the newest {} transactions of the package, older operations may be missing; see --scan",
+                                  history.scanned);
+                    }
+                }
+            }
+        }
+
+        Some(SuiS3Cmd::Export { uri, out: file }) => {
+            let Some(caps) = parse_uri(&uri) else {
+                errln!("SUIS3 object format error.");
+                return;
+            };
+            let bucket_name = caps.bucket.as_str();
+
+            match operations::get_bucket_objects(bucket_name.to_owned()).await {
+                Err(e) => failln!(e),
+                Ok(ret) => {
+                    let mut lines = String::new();
+                    let count = ret.objects.len();
+                    for object in ret.objects {
+                        let record = operations::ExportRecord {
+                            version: operations::EXPORT_VERSION,
+                            bucket: bucket_name.to_owned(),
+                            object,
+                        };
+                        lines += &serde_json::to_string(&record).unwrap();
+                        lines += "\n";
+                    }
+
+                    match file {
+                        None => {
+                            let _ = write!(out, "{}", lines);
+                        }
+                        Some(file) => match fs::write(&file, lines) {
+                            Err(e) => failln!(e),
+                            Ok(()) => statusln!(out, "Exported {} objects to: {}", count, file),
+                        },
+                    }
                 }
             }
         }
 
-        Some(SuiS3Cmd::Put { uri, file }) => {
-            let re = Regex::new(SUIS3_REGEXP).unwrap();
-            let caps = re.captures(&uri);
-            if caps.is_none() {
-                println!("SUIS3 object format error.");
+        Some(SuiS3Cmd::Import { uri, file, force, batch_size, dry_run }) => {
+            let Some(caps) = parse_uri(&uri) else {
+                errln!("SUIS3 object format error.");
                 return;
+            };
+            let bucket_name = caps.bucket.as_str();
+
+            let content = match fs::read_to_string(&file) {
+                Err(e) => {
+                    failln!(e);
+                    return;
+                }
+                Ok(content) => content,
+            };
+
+            let existing = match operations::get_bucket_objects(bucket_name.to_owned()).await {
+                Err(e) => {
+                    failln!(e);
+                    return;
+                }
+                Ok(ret) => ret.objects,
+            };
+
+            let (mut imported, mut skipped, mut failed) = (0, 0, 0);
+            let mut pending = Vec::new();
+            for (i, line) in content.lines().enumerate() {
+                if line.trim().len() == 0 {
+                    continue;
+                }
+                let record: operations::ExportRecord = match serde_json::from_str(line) {
+                    Err(e) => {
+                        errln!("Failed line {}: {}", i + 1, e);
+                        failed += 1;
+                        continue;
+                    }
+                    Ok(record) => record,
+                };
+                let obj = &record.object;
+                if record.version != operations::EXPORT_VERSION {
+                    errln!("Failed line {}: unsupported export version {}", i + 1, record.version);
+                    failed += 1;
+                    continue;
+                }
+                if !obj.uri.starts_with('/') || obj.walrus_blob_id.len() == 0 {
+                    errln!("Failed line {}: invalid object record", i + 1);
+                    failed += 1;
+                    continue;
+                }
+                if let Some(current) = existing.iter().find(|o| o.uri == obj.uri) {
+                    if !force {
+                        statusln!(out, "Skipped (exists): suis3://{}{}", bucket_name, obj.uri);
+                        skipped += 1;
+                        continue;
+                    }
+                    if let Err(e) = operations::ensure_unlocked(bucket_name, &obj.uri, &current.tags, false) {
+                        errln!("Failed line {}: {}", i + 1, e);
+                        failed += 1;
+                        continue;
+                    }
+                }
+
+                pending.push((i + 1, obj.uri.clone(), obj.to_blob_meta()));
             }
-            let caps = caps.unwrap();        
-            let bucket_name = &caps["bucket"];
-            let mut obj_name = caps["object"].to_owned();
 
-            if obj_name.len() == 0 || obj_name == "/" {
-                let path = Path::new(&file);
-                let filename = path.file_name().unwrap();
-                obj_name = "/".to_owned() + filename.to_str().unwrap();
-            } 
+            if dry_run {
+                for (_, uri, meta) in pending.iter() {
+                    outln!(out, "Would import: suis3://{}{} (blob id: {})", bucket_name, uri, meta.walrus_blob_id);
+                }
+                statusln!(out, "Would import {}, skipped {}, failed {}", pending.len(), skipped, failed);
+                return;
+            }
 
-            match operations::put_object(bucket_name, obj_name.as_str(), &file).await {
-                Err(e) => println!("{}", e),
-                Ok(meta) => {
-                    println!("Blob id: {}", meta.walrus_blob_id);
+            // the move calls of a transaction apply all or nothing,
+            // a failed batch is retried one object at a time to find the bad records
+            for batch in pending.chunks(batch_size.max(1)) {
+                if batch.len() > 1 {
+                    let objects = batch.iter().map(|(_, uri, meta)| (uri.clone(), meta.clone())).collect::<Vec<_>>();
+                    match operations::create_objects(bucket_name, &objects).await {
+                        Ok(digest) => {
+                            for (_, uri, _) in batch.iter() {
+                                statusln!(out, "Imported: suis3://{}{} ({})", bucket_name, uri, digest);
+                            }
+                            imported += batch.len();
+                            continue;
+                        }
+                        Err(e) => statusln!(out, "Batch of {} objects failed, retrying one by one: {}", batch.len(), e),
+                    }
+                }
+                for (line, uri, meta) in batch.iter() {
+                    match operations::create_object(bucket_name, uri, meta).await {
+                        Err(e) => {
+                            errln!("Failed line {}: {}", line, e);
+                            failed += 1;
+                        }
+                        Ok(digest) => {
+                            statusln!(out, "Imported: suis3://{}{} ({})", bucket_name, uri, digest);
+                            imported += 1;
+                        }
+                    }
                 }
             }
+            statusln!(out, "Imported {}, skipped {}, failed {}", imported, skipped, failed);
+        }
+
+        Some(SuiS3Cmd::Cp { src, dst, preserve }) => {
+            copy(out, src, dst, preserve).await;
         }
 
-        Some(SuiS3Cmd::Get { uri, file }) => {
-            let re = Regex::new(SUIS3_REGEXP).unwrap();
-            let caps = re.captures(&uri);
-            if caps.is_none() {
-                println!("SUIS3 object format error.");
+        Some(SuiS3Cmd::Mv { src, dst, no_preserve }) => {
+            if parse_uri(&src).is_none() || parse_uri(&dst).is_none() {
+                errln!("Both sides of mv must be SUIS3 objects (suis3://<bucket>/<object>).");
                 return;
             }
-            let caps = caps.unwrap();        
-            let bucket_name = &caps["bucket"];
-            let obj_name = &caps["object"];
+            copy_object(out, src, dst, !no_preserve, true).await;
+        }
+
+        Some(SuiS3Cmd::Repair { uri, within, concurrency, dry_run }) => repair_bucket(out, uri, within, concurrency, dry_run).await,
+
+        Some(SuiS3Cmd::Restore { uri, blob_id, size, epoch }) => {
+            let Some(caps) = parse_uri(&uri) else {
+                errln!("SUIS3 object format error.");
+                return;
+            };
+            let bucket_name = caps.bucket.as_str();
+            let obj_name = caps.object.as_str();
 
             if obj_name.len() == 0 {
-                println!("SUIS3 object format error.");
+                errln!("SUIS3 object format error.");
             } else {
-                let dest_filename;                
-                if file.is_none() {
-                    let path = Path::new(obj_name);
-                    let filename = path.file_name().unwrap();
-                    dest_filename = filename.to_str().unwrap().to_owned();    
-                } else {
-                    dest_filename = file.unwrap();
-                }
-                
-                match operations::get_object_id(bucket_name.to_owned(), obj_name.to_owned()).await {
-                    Err(e) => println!("{}", e),
-                    Ok(blob_id) => {
-                        match walrus::walrus_download_file(&blob_id, &dest_filename) {
-                            Err(e) => println!("{}", e),
-                            Ok(()) => {
-                                println!("Saved as: {}", dest_filename);
-                            }            
+                if let Ok(current) = operations::get_object(bucket_name.to_owned(), obj_name.to_owned()).await {
+                    if let Err(e) = operations::ensure_unlocked(bucket_name, obj_name, &current.tags, false) {
+                        errln!("{}", e);
+                        return;
+                    }
+                }
+                let meta = walrus::BlobMeta {
+                    size,
+                    tags: Vec::<String>::new(),
+                    last_write_ts: 0,
+                    walrus_blob_id: blob_id,
+                    walrus_epoch_till: epoch,
+                };
+
+                match operations::create_object(bucket_name, obj_name, &meta).await {
+                    Err(e) => failln!(e),
+                    Ok(digest) => {
+                        statusln!(out, "Restored: suis3://{}{}", bucket_name, obj_name);
+                        print_digest(out, &digest);
+                    }
+                }
+            }
+        }
+
+        Some(SuiS3Cmd::BlobStatus { blob_id }) => {
+            let status = tokio::task::spawn_blocking(move || walrus::backend().blob_status_detail(&blob_id))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|r| r);
+            match status {
+                Err(e) => failln!(e),
+                Ok(status) => {
+                    for (key, value) in status {
+                        match key.len() {
+                            0 => outln!(out, "{}", value),
+                            _ => outln!(out, "{}: {}", key, value),
                         }
                     }
-                }                
+                }
             }
         }
 
-        Some(SuiS3Cmd::Cat { uri }) => {
-            let re = Regex::new(SUIS3_REGEXP).unwrap();
-            let caps = re.captures(&uri);
-            if caps.is_none() {
-                println!("SUIS3 object format error.");
+        Some(SuiS3Cmd::Share { uri }) => {
+            let Some(caps) = parse_uri(&uri) else {
+                errln!("SUIS3 object format error.");
                 return;
+            };
+            let bucket_name = caps.bucket.as_str();
+            let obj_name = caps.object.as_str();
+
+            if obj_name.len() == 0 {
+                errln!("SUIS3 object format error.");
+            } else {
+                match operations::get_object_id(bucket_name.to_owned(), obj_name.to_owned()).await {
+                    Err(e) => failln!(e),
+                    Ok(blob_id) => {
+                        let base = settings::get().config.aggregator_url;
+                        outln!(out, "{}/v1/blobs/{}", base.trim_end_matches('/'), blob_id);
+                    }
+                }
             }
-            let caps = caps.unwrap();        
-            let bucket_name = &caps["bucket"];
-            let obj_name = &caps["object"];
+        }
+
+        Some(SuiS3Cmd::Cat { uri, limit, all, pretty }) => {
+            let Some(caps) = parse_uri(&uri) else {
+                errln!("SUIS3 object format error.");
+                return;
+            };
+            let bucket_name = caps.bucket.as_str();
+            let obj_name = caps.object.as_str();
 
             if obj_name.len() == 0 {
-                println!("SUIS3 object format error.");
+                errln!("SUIS3 object format error.");
             } else {
                 let dir = env::temp_dir();
-                let dest_filename = dir.to_str().unwrap().to_string() + "suis3_tmp";                
+                let dest_filename = dir.join("suis3_tmp").to_str().unwrap().to_string();
 
-                match operations::get_object_id(bucket_name.to_owned(), obj_name.to_owned()).await {
-                    Err(e) => println!("{}", e),
-                    Ok(blob_id) => {
-                        match walrus::walrus_download_file(&blob_id, &dest_filename) {
-                            Err(e) => println!("{}", e),
+                match operations::get_object(bucket_name.to_owned(), obj_name.to_owned()).await {
+                    Err(e) => failln!(e),
+                    Ok(meta) => {
+                        // checked on the stored size, a large blob is not even downloaded
+                        let max_size = limit.unwrap_or(CAT_MAX_SIZE);
+                        if !all && meta.size > max_size {
+                            errln!("Object suis3://{}{} is {} bytes, more than the {} bytes cat prints. Use get, or cat with --limit <bytes> or --all.",
+                                   bucket_name, obj_name, meta.size, max_size);
+                            return;
+                        }
+
+                        // objects without a content-type tag are printed as text, like before the tag existed
+                        let content_type = operations::tag_value(&meta.tags, operations::CONTENT_TYPE_TAG);
+                        let binary = content_type.is_some_and(|t| !utils::is_text_content_type(t));
+                        if binary && settings::get().interactive {
+                            let question = format!("Object suis3://{}{} is {}. Print it anyway?", bucket_name, obj_name, content_type.unwrap());
+                            if !confirm(&question) {
+                                return;
+                            }
+                        }
+
+                        // a compressed object is printed as the file put
+                        let compression = operations::tag_value(&meta.tags, operations::CONTENT_ENCODING_TAG);
+                        match download_blob(&meta.walrus_blob_id, meta.size, &dest_filename, compression) {
+                            Err(e) => failln!(e),
                             Ok(()) => {
-                                if let Ok(content) = fs::read_to_string(dest_filename.clone()) {
-                                    println!("{}", content);
-                                } 
+                                // rendered in memory, the size is bounded by the limit unless --all
+                                let rendered = if pretty && !binary {
+                                    let color = !settings::get().piped && std::io::stdout().is_terminal();
+                                    fs::read(&dest_filename)
+                                        .ok()
+                                        .and_then(|content| render::pretty(&content, content_type, obj_name, color))
+                                } else {
+                                    None
+                                };
+                                if let Some(rendered) = rendered {
+                                    outln!(out, "{}", rendered.trim_end_matches('\n'));
+                                    let _ = out.flush();
+                                    let _ = fs::remove_file(dest_filename);
+                                    return;
+                                }
+                                // stream in chunks rather than loading the whole object
+                                match fs::File::open(&dest_filename) {
+                                    Err(e) => failln!(e),
+                                    Ok(mut f) => {
+                                        let _ = std::io::copy(&mut f, &mut *out);
+                                        // binary content is copied as is, e.g. for `cat ... > file`
+                                        if !binary {
+                                            let _ = out.write_all(b"\n");
+                                        }
+                                        let _ = out.flush();
+                                    }
+                                }
                                 let _ = fs::remove_file(dest_filename);
-                            }            
+                            }
                         }
                     }
-                }                
+                }
             }
         }
 
-        Some(SuiS3Cmd::Del { uri }) 
-        | Some(SuiS3Cmd::Rm { uri }) => {
-            let re = Regex::new(SUIS3_REGEXP).unwrap();
-            let caps = re.captures(&uri);
-            if caps.is_none() {
-                println!("SUIS3 object format error.");
+        Some(SuiS3Cmd::Del { uri, force_unlock }) => {
+            let Some(caps) = parse_uri(&uri) else {
+                errln!("SUIS3 object format error.");
                 return;
-            }
-            let caps = caps.unwrap();        
-            let bucket_name = &caps["bucket"];
-            let obj_name = &caps["object"];
+            };
+            let bucket_name = caps.bucket.as_str();
+            let obj_name = caps.object.as_str();
 
             if obj_name.len() == 0 {
-                println!("SUIS3 object format error.");
+                errln!("SUIS3 object format error.");
             } else {
+                // the walrus blob outlives the metadata, keep what is needed to restore it
+                let meta = match operations::get_object(bucket_name.to_owned(), obj_name.to_owned()).await {
+                    Err(e) => {
+                        failln!(e);
+                        return;
+                    }
+                    Ok(meta) => meta,
+                };
+                if let Err(e) = operations::ensure_unlocked(bucket_name, obj_name, &meta.tags, force_unlock) {
+                    errln!("{} or delete with --force-unlock", e);
+                    return;
+                }
+
                 match operations::delete_object(bucket_name.to_owned(), obj_name.to_owned()).await {
-                    Err(e) => println!("{}", e),
-                    Ok(()) => {}
+                    Err(e) => failln!(e),
+                    Ok(digest) => {
+                        statusln!(out, "Deleted metadata for suis3://{}{} (blob id: {}, expires at epoch {}; recover with `restore`)",
+                                 bucket_name, obj_name, meta.walrus_blob_id, meta.walrus_epoch_till);
+                        statusln!(out, "    restore suis3://{}{} {} {} {}",
+                                 bucket_name, obj_name, meta.walrus_blob_id, meta.size, meta.walrus_epoch_till);
+                        print_digest(out, &digest);
+                    }
                 }
             }
         }
 
 
-        Some(SuiS3Cmd::List { uri }) => {
-            if uri.is_none() {
-                // list all buckets
+        Some(SuiS3Cmd::Select { uri }) => select_delete(out, uri).await,
+        Some(SuiS3Cmd::Refresh { uri }) => refresh(out, uri).await,
+        Some(SuiS3Cmd::Complete { word }) => match cache::complete(word.as_deref().unwrap_or("")).await {
+            Err(e) => failln!(e),
+            Ok(uris) => {
+                for uri in uris {
+                    outln!(out, "{}", uri);
+                }
+            }
+        },
+
+        Some(SuiS3Cmd::List { uri, limit, start_after, tags, any, after, before, porcelain, keys_only, count }) => {
+            if uri.is_none() && count {
+                print_bucket_count(out).await;
+            } else if uri.is_none() && keys_only {
                 match operations::list_buckets().await {
-                    Err(e) => println!("{}", e),
-                    Ok(v) => {
-                        println!("TIME\t\t\t\tBUCKET NAME");
-                        for bi in v.iter() {
-                            let timestamp = NaiveDateTime::from_timestamp((bi.create_ts/1000) as i64, 0);
-                            let date_time: DateTime<Local> = Local.from_local_datetime(&timestamp).unwrap();
-                            println!("{}\t{}", date_time, bi.name);
+                    Err(e) => failln!(e),
+                    Ok(buckets) => {
+                        for bucket in buckets {
+                            outln!(out, "suis3://{}", bucket.name);
                         }
                     }
-                }    
+                }
+            } else if uri.is_none() {
+                // list all buckets
+                print_buckets(out, false, porcelain, false).await;
             } else {
                 // list one bucket
                 let uri = uri.unwrap();
-                let re = Regex::new(SUIS3_REGEXP).unwrap();
-                let caps = re.captures(&uri);
-                if caps.is_none() {
-                    println!("SUIS3 object format error.");
+                let Some(caps) = parse_uri(&uri) else {
+                    errln!("SUIS3 object format error.");
                     return;
-                }
-                let caps = caps.unwrap();                
-                let bucket_name = &caps["bucket"];
-                let obj_name = &caps["object"];
+                };
+                let bucket_name = caps.bucket.as_str();
+                let obj_name = caps.object.as_str();
     
-                if obj_name.len() != 0 {
-                    println!("SUIS3 object format error.");
-                } else {
-                    match operations::get_bucket_objects(bucket_name.to_owned()).await {
-                        Err(e) => println!("{}", e),
-                        Ok(ret) => {
-                            println!("URI\t\t\tTIME");
+                match operations::get_bucket_objects(bucket_name.to_owned()).await {
+                    Err(e) => failln!(e),
+                    Ok(mut ret) => {
+                        // the whole listing, before the filters, completes the object names
+                        cache::save_objects(bucket_name, ret.objects.iter().map(|obj| obj.uri.clone()).collect());
+                        // a missing bucket fails above, an empty one is said so
+                        if ret.objects.is_empty() && !count {
+                            note_empty_bucket(bucket_name);
+                        }
+                        // an object in the uri lists just that object
+                        if obj_name.len() != 0 {
+                            ret.objects.retain(|obj| obj.uri == obj_name);
+                            if ret.objects.is_empty() {
+                                failln!(anyhow::Error::from(operations::SuiS3Error::ObjectNotFound(bucket_name.to_owned(), obj_name.to_owned())));
+                                return;
+                            }
+                        }
+                        // the page is taken from the matching objects
+                        ret.filter_tags(&tags, any);
+                        ret.filter_time(after, before);
+                        let truncated = if limit.is_some() || start_after.is_some() {
+                            ret.paginate(start_after.as_deref(), limit)
+                        } else {
+                            false
+                        };
+                        if count {
+                            outln!(out, "{}", ret.objects.len());
+                            return;
+                        }
+                        if keys_only {
+                            for obj in ret.objects.iter() {
+                                outln!(out, "suis3://{}{}", bucket_name, obj.uri);
+                            }
+                            if truncated {
+                                note_more_objects(&ret);
+                            }
+                            return;
+                        }
+
+                        if porcelain {
                             for obj in ret.objects.iter() {
-                                let timestamp = NaiveDateTime::from_timestamp((obj.last_write_ts/1000) as i64, 0);
-                                let date_time: DateTime<Local> = Local.from_local_datetime(&timestamp).unwrap();
-                                println!("{}\t{}", obj.uri, date_time);                                                               
+                                let _ = write!(out, "{}", porcelain::object(obj, false));
+                            }
+                            if truncated {
+                                note_more_objects(&ret);
                             }
+                            return;
                         }
-                    }                    
+
+                        outln!(out, "URI\t\t\tTIME");
+                        for obj in ret.objects.iter() {
+                            let date_time = utils::display_time(obj.last_write_ts);
+                            outln!(out, "{}\t{}", obj.uri, date_time);                                                               
+                        }
+                        if truncated {
+                            print_more_objects(out, &ret);
+                        }
+                    }
                 }
             }
         },
-        Some(SuiS3Cmd::Detail { uri }) => {
-            if uri.is_none() {
+        Some(SuiS3Cmd::Detail { uri, limit, start_after, tags, any, after, before, porcelain, json, full_blob_id, count }) => {
+            if uri.is_none() && json {
+                errln!("--json lists the objects of a bucket, e.g. ll suis3://<bucket> --json");
+            } else if uri.is_none() && count {
+                print_bucket_count(out).await;
+            } else if uri.is_none() {
                 // list all buckets
-                match operations::list_buckets().await {
-                    Err(e) => println!("{}", e),
-                    Ok(v) => {
-                        println!("TIME\t\t\t\tBUCKET NAME");
-                        for bi in v.iter() {
-                            let timestamp = NaiveDateTime::from_timestamp((bi.create_ts/1000) as i64, 0);
-                            let date_time: DateTime<Local> = Local.from_local_datetime(&timestamp).unwrap();
-                            println!("{}\t{}", date_time, bi.name);
-                        }
-                    }
-                }    
+                print_buckets(out, true, porcelain, false).await;
             } else {
                 // list one bucket
                 let uri = uri.unwrap();
-                let re = Regex::new(SUIS3_REGEXP).unwrap();
-                let caps = re.captures(&uri);
-                if caps.is_none() {
-                    println!("SUIS3 object format error.");
+                let Some(caps) = parse_uri(&uri) else {
+                    errln!("SUIS3 object format error.");
                     return;
-                }
-                let caps = caps.unwrap();
-                let bucket_name = &caps["bucket"];
-                let obj_name = &caps["object"];
+                };
+                let bucket_name = caps.bucket.as_str();
+                let obj_name = caps.object.as_str();
     
-                if obj_name.len() != 0 {
-                    println!("SUIS3 object format error.");
-                } else {
-                    match operations::get_bucket_objects(bucket_name.to_owned()).await {
-                        Err(e) => println!("{}", e),
-                        Ok(ret) => {
-                            println!("URI\t\t\tTIME\t\t\tSIZE\tBLOB ID\t\t\t\t\t\tTILL EPOCH");
+                match operations::get_bucket_objects(bucket_name.to_owned()).await {
+                    Err(e) => failln!(e),
+                    Ok(mut ret) => {
+                        // the whole listing, before the filters, completes the object names
+                        cache::save_objects(bucket_name, ret.objects.iter().map(|obj| obj.uri.clone()).collect());
+                        // a missing bucket fails above, an empty one is said so
+                        if ret.objects.is_empty() && !count {
+                            note_empty_bucket(bucket_name);
+                        }
+                        // an object in the uri lists just that object
+                        if obj_name.len() != 0 {
+                            ret.objects.retain(|obj| obj.uri == obj_name);
+                            if ret.objects.is_empty() {
+                                failln!(anyhow::Error::from(operations::SuiS3Error::ObjectNotFound(bucket_name.to_owned(), obj_name.to_owned())));
+                                return;
+                            }
+                        }
+                        // the page is taken from the matching objects
+                        ret.filter_tags(&tags, any);
+                        ret.filter_time(after, before);
+                        let truncated = if limit.is_some() || start_after.is_some() {
+                            ret.paginate(start_after.as_deref(), limit)
+                        } else {
+                            false
+                        };
+                        if count {
+                            outln!(out, "{}", ret.objects.len());
+                            return;
+                        }
+
+                        if json {
+                            for obj in ret.objects.iter() {
+                                print_json(out, obj);
+                            }
+                            if truncated {
+                                note_more_objects(&ret);
+                            }
+                            return;
+                        }
+                        if porcelain {
                             for obj in ret.objects.iter() {
-                                let timestamp = NaiveDateTime::from_timestamp((obj.last_write_ts/1000) as i64, 0);
-                                let date_time: DateTime<Local> = Local.from_local_datetime(&timestamp).unwrap();
-                                println!("{}\t{}\t{}\t{}\t{}", obj.uri, date_time, obj.size, obj.walrus_blob_id, obj.walrus_epoch_till);                                                               
+                                let _ = write!(out, "{}", porcelain::object(obj, true));
+                            }
+                            if truncated {
+                                note_more_objects(&ret);
                             }
+                            return;
                         }
-                    }                    
+
+                        let rows = ret.objects.iter().map(|obj| {
+                            vec![
+                                obj.uri.clone(),
+                                utils::display_time(obj.last_write_ts),
+                                humansize::format_size(obj.size, humansize::BINARY),
+                                operations::tag_value(&obj.tags, operations::CONTENT_TYPE_TAG).unwrap_or("-").to_owned(),
+                                blob_id_display(&obj.walrus_blob_id, full_blob_id),
+                                obj.walrus_epoch_till.to_string(),
+                            ]
+                        }).collect::<Vec<_>>();
+                        print_table(out, &["URI", "TIME", "SIZE", "CONTENT TYPE", "BLOB ID", "TILL EPOCH"], &rows, &[false, false, true, false, false, true]);
+                        if truncated {
+                            print_more_objects(out, &ret);
+                        }
+                    }
                 }
             }            
         }
         
+        Some(SuiS3Cmd::Config { action: ConfigAction::List, .. })
+        | Some(SuiS3Cmd::Config { action: ConfigAction::Ls, .. }) => {
+            for (key, value, source) in settings::get().config.origins.iter() {
+                outln!(out, "{} = {}\t({})", key, value, source);
+            }
+        }
+
+        Some(SuiS3Cmd::Config { action: ConfigAction::Get, key, .. }) => {
+            let Some(key) = key else {
+                errln!("Config key is required.");
+                return;
+            };
+            match settings::get().config.origins.iter().find(|(k, _, _)| *k == key) {
+                None => errln!("Unknown config key {}, expected one of {}", key, settings::CONFIG_KEYS.join(", ")),
+                Some((_, value, _)) => outln!(out, "{}", value),
+            }
+        }
+
+        Some(SuiS3Cmd::Config { action, key, value }) => {
+            let Some(key) = key else {
+                errln!("Config key is required.");
+                return;
+            };
+            if action == ConfigAction::Set && value.is_none() {
+                errln!("Config value is required.");
+                return;
+            }
+            let value = if action == ConfigAction::Set { value } else { None };
+
+            let result = settings::ConfigValues::load().and_then(|mut file| {
+                file.set(&key, value.as_deref())?;
+                file.save()?;
+                Ok(file)
+            });
+            match result {
+                Err(e) => failln!(e),
+                Ok(file) => {
+                    settings::reload(&file);
+                    let effective = settings::get().config;
+                    let (_, value, source) = effective.origins.iter().find(|(k, _, _)| *k == key).unwrap();
+                    statusln!(out, "{} = {}\t({})", key, value, source);
+                }
+            }
+        }
+
+        #[cfg(feature = "serve")]
+        Some(SuiS3Cmd::Serve { bucket, port }) => {
+            let Some(caps) = parse_uri(&bucket) else {
+                errln!("SUIS3 object format error.");
+                return;
+            };
+            if let Err(e) = serve::serve(caps.bucket, port).await {
+                failln!(e);
+            }
+        }
+
+        Some(SuiS3Cmd::Lock { uri }) => set_lock(out, uri, true).await,
+        Some(SuiS3Cmd::Unlock { uri }) => set_lock(out, uri, false).await,
+        Some(SuiS3Cmd::CopyTags { src, dst, replace, .. }) => copy_tags(out, src, dst, replace).await,
+
+        Some(SuiS3Cmd::Doctor) => doctor(out).await,
+
         None | Some(SuiS3Cmd::Quit) => (), // handle in main loop
     }
 }
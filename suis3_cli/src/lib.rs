@@ -1,3 +1,10 @@
+pub mod cache;
+pub mod client;
 pub mod operations;
+pub mod porcelain;
+pub mod render;
+pub mod settings;
 pub mod utils;
 pub mod walrus;
+
+pub use client::{SuiS3Client, SuiS3ClientBuilder};
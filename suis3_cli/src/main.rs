@@ -1,48 +1,380 @@
 
 use std::fs::OpenOptions;
-use std::io::stdout;
+use std::io::{stdout, Stdout};
 use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
 
 use clap::{CommandFactory, Parser};
 use colored::{self, *};
 use log::LevelFilter;
-use regex::Regex;
 
-use command::{do_command, Cli, SuiS3Cmd};
+use command::{do_command, parse_uri, Cli, ConfigAction, ShellLine, SuiS3Cmd};
 use logger::Logger;
+use settings::Settings;
 
+// the operations come from the suis3 library, the binary adds the commands on top
+use suis3::{cache, operations, porcelain, render, settings, utils, walrus};
+
+#[macro_use]
 mod command;
 mod logger;
-mod utils;
-mod operations;
-mod walrus;
+#[cfg(feature = "serve")]
+mod serve;
 
 static MY_LOGGER: Logger = Logger;
 
+// an error of the shell itself rather than of a command, on stderr like theirs. the shell line fails with 1
+fn shell_error(exit_code: &mut i32, message: impl std::fmt::Display) {
+    eprintln!("{}", message);
+    *exit_code = 1;
+}
+
+// list every command with its usage lines and aliases, or the full help of one command
+fn print_help(out: &mut dyn Write, name: Option<&str>) -> Result<(), String> {
+    let mut command = <SuiS3Cmd as CommandFactory>::command();
+    match name {
+        None => {
+            for sub in command.get_subcommands() {
+                let mut names = vec![sub.get_name().to_owned()];
+                names.extend(sub.get_visible_aliases().map(|a| a.to_owned()));
+                let about = sub.get_about().map(|a| a.to_string()).unwrap_or_default();
+                let mut lines = about.lines();
+                outln!(out, "{:<16}{}", names.join("/"), lines.next().unwrap_or(""));
+                for line in lines {
+                    outln!(out, "{:<16}{}", "", line);
+                }
+            }
+            outln!(out, "{:<16}{}", "cd", "enter the bucket, ls/ll and the object commands then take bare object names");
+            outln!(out, "{:<16}{}", "", "    cd s3://<bucket>");
+            outln!(out, "{:<16}{}", "", "leave the bucket");
+            outln!(out, "{:<16}{}", "", "    cd ..");
+            outln!(out, "{:<16}{}", "pwd", "show the entered bucket");
+            outln!(out);
+            outln!(out, "Type `help <command>` for the arguments and options of one command.");
+        }
+        Some(name) => match command.find_subcommand_mut(name) {
+            None => return Err(format!("Unknown command {}, type `help` to list the commands.", name)),
+            Some(sub) => outln!(out, "{}", sub.render_long_help()),
+        },
+    }
+    Ok(())
+}
+
+// state of the interactive shell, kept across its commands
+#[derive(Default)]
+struct Shell {
+    // the bucket entered with `cd`, bare object names are resolved against it
+    bucket: Option<String>,
+}
+
+impl Shell {
+    fn prompt(&self) -> String {
+        let network = settings::get().config.network;
+        match &self.bucket {
+            None => format!("suis3({})", network),
+            Some(bucket) => format!("suis3({}):{}", network, bucket),
+        }
+    }
+
+    // `cd suis3://<bucket>` enters the bucket, `cd ..` or `cd /` leaves it
+    async fn cd(&mut self, arg: Option<&str>) -> Result<(), String> {
+        if let Some(".." | "/") = arg {
+            self.bucket = None;
+            return Ok(());
+        }
+        let Some(caps) = arg.and_then(parse_uri) else {
+            return Err("Usage: cd suis3://<bucket>, or cd .. to leave the bucket".to_owned());
+        };
+        if caps.object.len() != 0 {
+            return Err("SUIS3 object format error.".to_owned());
+        }
+        match operations::bucket_exists(&caps.bucket).await {
+            Err(e) => Err(e.to_string()),
+            Ok(false) => Err(format!("Bucket suis3://{} does not exist.", caps.bucket)),
+            Ok(true) => {
+                self.bucket = Some(caps.bucket);
+                Ok(())
+            }
+        }
+    }
+
+    fn pwd(&self, out: &mut dyn Write) {
+        match &self.bucket {
+            None => outln!(out, "No bucket entered, see `cd`."),
+            Some(bucket) => outln!(out, "suis3://{}", bucket),
+        }
+    }
+
+    // the entered bucket may be gone after rb, rename-bucket or a switch of network,
+    // the shell follows a renamed bucket and leaves a deleted one
+    async fn recheck(&mut self, out: &mut dyn Write, renamed: Option<(String, String)>) {
+        let Some(name) = self.bucket.clone() else {
+            return;
+        };
+        if let Ok(true) = operations::bucket_exists(&name).await {
+            return;
+        }
+        self.bucket = match renamed {
+            Some((old, new)) if old == name => Some(new),
+            _ => None,
+        };
+        if self.bucket.is_none() {
+            outln!(out, "Left suis3://{}, it is not found any more.", name);
+        }
+    }
+
+    // a full uri is kept as is, a bare object name is taken inside the entered bucket
+    fn object_uri(&self, uri: String) -> Result<String, String> {
+        if parse_uri(&uri).is_some() {
+            return Ok(uri);
+        }
+        match &self.bucket {
+            Some(bucket) => Ok(format!("suis3://{}/{}", bucket, uri.trim_start_matches('/'))),
+            None => Err(format!("{} is not a suis3:// uri and no bucket is entered, see `cd`.", uri)),
+        }
+    }
+
+    // ls/ll without a uri list the entered bucket, the object commands take bare object names
+    fn resolve(&self, cmd: SuiS3Cmd) -> Result<SuiS3Cmd, String> {
+        let cmd = match cmd {
+            SuiS3Cmd::List { uri: None, limit, start_after, tags, any, after, before, porcelain, keys_only, count } if self.bucket.is_some() => SuiS3Cmd::List {
+                uri: Some(format!("suis3://{}", self.bucket.as_ref().unwrap())),
+                limit,
+                start_after,
+                tags,
+                any,
+                after,
+                before,
+                porcelain,
+                keys_only,
+                count,
+            },
+            SuiS3Cmd::Detail { uri: None, limit, start_after, tags, any, after, before, porcelain, json, full_blob_id, count } if self.bucket.is_some() => SuiS3Cmd::Detail {
+                uri: Some(format!("suis3://{}", self.bucket.as_ref().unwrap())),
+                limit,
+                start_after,
+                tags,
+                any,
+                after,
+                before,
+                porcelain,
+                json,
+                full_blob_id,
+                count,
+            },
+            SuiS3Cmd::Put { file, uri, create_bucket, content_type, full_blob_id, tags, no_inherit, compress, dedup, recursive, overlap, name, versioned,
+                            force_unlock } => SuiS3Cmd::Put {
+                file,
+                uri: self.object_uri(uri)?,
+                create_bucket,
+                content_type,
+                full_blob_id,
+                tags,
+                no_inherit,
+                compress,
+                dedup,
+                recursive,
+                overlap,
+                name,
+                versioned,
+                force_unlock,
+            },
+            SuiS3Cmd::Get { uri, file, recursive, concurrency, dry_run, preserve_path, no_clobber, force, decompress, retry_on_expiry, version } if !recursive => SuiS3Cmd::Get {
+                uri: self.object_uri(uri)?,
+                file,
+                recursive,
+                concurrency,
+                dry_run,
+                preserve_path,
+                no_clobber,
+                force,
+                decompress,
+                retry_on_expiry,
+                version,
+            },
+            SuiS3Cmd::Cat { uri, limit, all, pretty } => SuiS3Cmd::Cat {
+                uri: self.object_uri(uri)?,
+                limit,
+                all,
+                pretty,
+            },
+            SuiS3Cmd::Select { uri: None } if self.bucket.is_some() => SuiS3Cmd::Select {
+                uri: Some(format!("suis3://{}", self.bucket.as_ref().unwrap())),
+            },
+            SuiS3Cmd::Refresh { uri: None } if self.bucket.is_some() => SuiS3Cmd::Refresh {
+                uri: Some(format!("suis3://{}", self.bucket.as_ref().unwrap())),
+            },
+            SuiS3Cmd::Share { uri } => SuiS3Cmd::Share { uri: self.object_uri(uri)? },
+            SuiS3Cmd::Del { uri, force_unlock } => SuiS3Cmd::Del {
+                uri: self.object_uri(uri)?,
+                force_unlock,
+            },
+            SuiS3Cmd::Lock { uri } => SuiS3Cmd::Lock { uri: self.object_uri(uri)? },
+            SuiS3Cmd::Unlock { uri } => SuiS3Cmd::Unlock { uri: self.object_uri(uri)? },
+            SuiS3Cmd::CopyTags { src, dst, merge, replace } => SuiS3Cmd::CopyTags {
+                src: self.object_uri(src)?,
+                dst: self.object_uri(dst)?,
+                merge,
+                replace,
+            },
+            SuiS3Cmd::Restore { uri, blob_id, size, epoch } => SuiS3Cmd::Restore {
+                uri: self.object_uri(uri)?,
+                blob_id,
+                size,
+                epoch,
+            },
+            SuiS3Cmd::Tag { action, uri, tags, from_file, batch_size, force_unlock } => SuiS3Cmd::Tag {
+                action,
+                uri: self.object_uri(uri)?,
+                tags,
+                from_file,
+                batch_size,
+                force_unlock,
+            },
+            cmd => cmd,
+        };
+        Ok(cmd)
+    }
+}
+
+// the output of a shell line, stdout or the stdin of the shell command after `|`
+enum Output {
+    Stdout(Stdout),
+    Pipe(Child, ChildStdin),
+}
+
+impl Output {
+    fn open(pipe: Option<String>) -> Result<Output, std::io::Error> {
+        let Some(pipe) = pipe else {
+            return Ok(Output::Stdout(stdout()));
+        };
+        let mut child = Command::new("sh").arg("-c").arg(pipe).stdin(Stdio::piped()).spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        Ok(Output::Pipe(child, stdin))
+    }
+
+    // close the pipe so the shell command sees the end of its input, and wait for it
+    fn close(self) {
+        if let Output::Pipe(mut child, stdin) = self {
+            drop(stdin);
+            let _ = child.wait();
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Stdout(out) => out.write(buf),
+            Output::Pipe(_, stdin) => stdin.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Stdout(out) => out.flush(),
+            Output::Pipe(_, stdin) => stdin.flush(),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     log::set_logger(&MY_LOGGER).unwrap();
     log::set_max_level(LevelFilter::Warn);
 
     let mut matches = Cli::parse();
+    if matches.verbose {
+        log::set_max_level(LevelFilter::Info);
+    }
+    if matches.dump_events {
+        log::set_max_level(LevelFilter::Debug);
+    }
 
     let mut interactive: bool;
     if matches.suis3_cmd.is_some() {
         interactive = false;
-        let args: Vec<String> = std::env::args().collect();
-        matches.suis3_cmd = Some(SuiS3Cmd::parse_from(args[0..].iter()));
     } else {
         interactive = true;
     };
 
+    let flags = matches.config_flags();
+    let env = settings::ConfigValues::from_env()?;
+    let config = settings::Config::resolve(&flags, &env, &settings::ConfigValues::load()?);
+    settings::set(Settings {
+        no_wait: matches.no_wait,
+        interactive,
+        quiet: matches.quiet,
+        yes: matches.yes,
+        no_preflight: matches.no_preflight,
+        verbose: matches.verbose,
+        json_pretty: matches.json_pretty,
+        no_cache: matches.no_cache,
+        // a bare flag reads from the aggregator of the share links
+        read_aggregator: matches.endpoint_walrus_aggregator.clone().map(|url| url.unwrap_or(config.aggregator_url.clone())),
+        profile: matches.profile.clone(),
+        wallet_dir: None,
+        read_only_wallet: matches.read_only_wallet,
+        active_address: matches.assume_active_address,
+        gas_coin: matches.gas_coin,
+        show_balance: matches.show_balance,
+        budget_from_dryrun: matches.budget_from_dryrun,
+        budget_margin: matches.budget_margin,
+        flags,
+        env,
+        config,
+    });
+
+    // only a walrus binary configured on purpose is checked, the default one may legitimately be absent for listings
+    let config = settings::get().config;
+    if config.origins.iter().any(|(k, _, source)| *k == "walrus_bin" && *source != "default") {
+        if let Err(e) = walrus::locate_walrus_bin(&config.walrus_bin) {
+            log::warn!("{}", e);
+        }
+    }
+    // a walrus found is asked for its version, an incompatible one would fail later on its output
+    if walrus::locate_walrus_bin(&config.walrus_bin).is_ok() {
+        if let Err(e) = walrus::probe_walrus_version() {
+            log::warn!("Cannot get the walrus version: {}", e);
+        }
+    }
+
     let mut command = String::new();
+    let mut shell = Shell::default();
+    // the shell command after `|` on the last line of the shell
+    let mut pipe: Option<String> = None;
+    // a failed command given on the command line exits non-zero, the shell goes on and exits with the status of its last line
+    let mut exit_code = 0;
     while matches.suis3_cmd != Some(SuiS3Cmd::Quit) {
         stdout().flush().expect("Could not flush stdout");
 
         if command.starts_with("exit") || command.starts_with("quit") {
             interactive = false;
-        } else {
-            do_command(matches.suis3_cmd.take()).await;
+        } else if matches.suis3_cmd.is_some() {
+            let cmd = matches.suis3_cmd.take();
+            let recheck = matches!(
+                cmd,
+                Some(SuiS3Cmd::DeleteBucket { .. })
+                    | Some(SuiS3Cmd::RenameBucket { .. })
+                    | Some(SuiS3Cmd::Config { action: ConfigAction::Set | ConfigAction::Unset, .. })
+            );
+            let renamed = match &cmd {
+                Some(SuiS3Cmd::RenameBucket { old, new, .. }) => {
+                    parse_uri(old).zip(parse_uri(new)).map(|(old, new)| (old.bucket, new.bucket))
+                }
+                _ => None,
+            };
+            settings::set_piped(pipe.is_some());
+            match Output::open(pipe.take()) {
+                Err(e) => shell_error(&mut exit_code, e),
+                Ok(mut out) => {
+                    exit_code = do_command(cmd, &mut out).await;
+                    if recheck {
+                        shell.recheck(&mut out, renamed).await;
+                    }
+                    out.close();
+                }
+            }
         }
 
         if !interactive {
@@ -52,8 +384,9 @@ async fn main() -> Result<(), anyhow::Error> {
         command = match OpenOptions::new().read(true).write(true).open("/dev/tty") {
             Ok(mut tty) => {
                 tty.flush().expect("Could not open tty");
+                // rebuilt every time, `cd`, rb or `config set network` show up right away
                 let _ = tty.write_all(
-                    format!("{} {} ", "suis3".green(), ">".green())
+                    format!("{} {} ", shell.prompt().green(), ">".green())
                         .as_bytes(),
                 );
                 let reader = BufReader::new(&tty);
@@ -61,40 +394,74 @@ async fn main() -> Result<(), anyhow::Error> {
                 command_iter.next().unwrap_or("logout".to_string())
             }
             Err(e) => {
-                println!("{:?}", e);
+                shell_error(&mut exit_code, format!("Cannot open the terminal: {}", e));
                 "quit".to_string()
             }
         };
 
-        matches.suis3_cmd = if command.starts_with("help") {
-            let mut usage = Vec::<u8>::new();
-            let mut command = <SuiS3Cmd as CommandFactory>::command();
-            command
-                .write_help(&mut usage)
-                .expect("fail to get help of program");
-            let usage = unsafe { std::str::from_utf8_unchecked(&usage) };
-            let mut after_match = false;
-            let re = Regex::new("Commands:").unwrap();
-            let option_re = Regex::new("Options:").unwrap();
-            for line in usage.split('\n') {
-                if option_re.is_match(line) {
-                    break;
-                }
-                if after_match {
-                    println!("{}", line);
-                }
-                if !after_match && re.is_match(line) {
-                    after_match = true;
+        // the first `|` outside quotes starts the pipe
+        let (line, rest) = utils::split_pipe(&command);
+        pipe = rest.map(|rest| rest.trim().to_owned());
+        let words = utils::shell_words(line);
 
+        matches.suis3_cmd = if pipe.as_deref() == Some("") {
+            shell_error(&mut exit_code, "A shell command is expected after |");
+            pipe = None;
+            None
+        } else if words.is_none() {
+            shell_error(&mut exit_code, format!("A quote is not closed in: {}", line.trim()));
+            pipe = None;
+            None
+        } else if let Some(builtin @ ("help" | "cd" | "pwd")) = words.as_deref().unwrap().first().map(String::as_str) {
+            // the commands of the shell itself, run right away
+            match Output::open(pipe.take()) {
+                Err(e) => shell_error(&mut exit_code, e),
+                Ok(mut out) => {
+                    let arg = words.as_deref().unwrap().get(1).map(String::as_str);
+                    let result = match builtin {
+                        "help" => print_help(&mut out, arg),
+                        "cd" => shell.cd(arg).await,
+                        _ => {
+                            shell.pwd(&mut out);
+                            Ok(())
+                        }
+                    };
+                    out.close();
+                    match result {
+                        Err(e) => shell_error(&mut exit_code, e),
+                        Ok(()) => exit_code = 0,
+                    }
                 }
             }
             None
         } else {
-            let mut new_s3_cmd = vec![""];
-            new_s3_cmd.append(&mut command.split_whitespace().collect());
-            SuiS3Cmd::try_parse_from(new_s3_cmd).ok()
+            // -y/--yes on a shell line answers the prompts of that line
+            let mut new_s3_cmd = vec![String::new()];
+            new_s3_cmd.append(&mut words.unwrap());
+            match ShellLine::try_parse_from(new_s3_cmd).map(|line| (line.yes, shell.resolve(line.suis3_cmd))) {
+                Ok((yes, Ok(cmd))) => {
+                    settings::set_yes(matches.yes || yes);
+                    Some(cmd)
+                }
+                Ok((_, Err(e))) => {
+                    shell_error(&mut exit_code, e);
+                    None
+                }
+                // a usage error exits 2 outside the shell, help and version 0
+                Err(e) => {
+                    let _ = e.print();
+                    exit_code = e.exit_code();
+                    None
+                }
+            }
         };
+        if matches.suis3_cmd.is_none() {
+            pipe = None;
+        }
     }
 
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
     Ok(())
 }
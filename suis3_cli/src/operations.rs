@@ -1,5 +1,13 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Instant;
+
 use anyhow::anyhow;
-use serde::Deserialize;
+use regex::Regex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_with::{DisplayFromStr, serde_as};
 
 use sui_sdk::{
@@ -14,17 +22,99 @@ use sui_sdk::{
         Identifier,
     },
 };
-use sui_keys::keystore::{AccountKeystore, FileBasedKeystore};
+use sui_keys::keystore::AccountKeystore;
 
-use sui_json_rpc_types::SuiObjectDataOptions;
+use sui_json_rpc_types::{
+    SuiArgument, SuiCallArg, SuiCommand, SuiExecutionStatus, SuiObjectDataOptions, SuiProgrammableTransactionBlock,
+    SuiTransactionBlockDataAPI, SuiTransactionBlockEffectsAPI, SuiTransactionBlockKind, SuiTransactionBlockResponse,
+    SuiTransactionBlockResponseQuery, TransactionFilter,
+};
+use sui_types::digests::TransactionDigest;
 use sui_types::transaction::ObjectArg;
 use shared_crypto::intent::Intent;
-use sui_config::{sui_config_dir, SUI_KEYSTORE_FILENAME};
+use crate::settings;
 use crate::utils;
 use crate::walrus;
 
-const PACKAGE_ID :&str = "0xaf4ce64ef7dad2b25ae3dc27165e7f7d238d046206c9a4f78dceea4cce8bd462";
-const BUCKETS_ROOT :&str = "0xe3cf1909b8f9311fbfeb72ffd7f49cb30830abe5f16b7747394f970d6c2711c5";
+// the failures a caller may want to tell apart, the rest stays an anyhow message
+#[derive(Debug)]
+pub enum SuiS3Error {
+    BucketNotFound(String),
+    // bucket, object
+    ObjectNotFound(String, String),
+    WalrusFailure(String),
+    // the active address
+    InsufficientGas(String),
+    RpcError(String),
+    TransactionFailed(String),
+}
+
+impl SuiS3Error {
+    // the exit code of the cli, 1 is any other error and 2 a usage error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SuiS3Error::BucketNotFound(_) => 3,
+            SuiS3Error::ObjectNotFound(..) => 4,
+            SuiS3Error::WalrusFailure(_) => 5,
+            SuiS3Error::InsufficientGas(_) => 6,
+            SuiS3Error::RpcError(_) => 7,
+            SuiS3Error::TransactionFailed(_) => 8,
+        }
+    }
+
+    // the name of the variant, the kind of the --json error records
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SuiS3Error::BucketNotFound(_) => "BucketNotFound",
+            SuiS3Error::ObjectNotFound(..) => "ObjectNotFound",
+            SuiS3Error::WalrusFailure(_) => "WalrusFailure",
+            SuiS3Error::InsufficientGas(_) => "InsufficientGas",
+            SuiS3Error::RpcError(_) => "RpcError",
+            SuiS3Error::TransactionFailed(_) => "TransactionFailed",
+        }
+    }
+}
+
+impl std::fmt::Display for SuiS3Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SuiS3Error::BucketNotFound(bucket) => write!(f, "Bucket suis3://{} does not exist.", bucket),
+            SuiS3Error::ObjectNotFound(bucket, object) => write!(f, "Object suis3://{}{} does not exist.", bucket, object),
+            SuiS3Error::WalrusFailure(message) => write!(f, "{}", message),
+            SuiS3Error::InsufficientGas(address) => {
+                write!(f, "No coin of {} holds the 5000000 MIST needed for gas, send SUI to the address", address)
+            }
+            SuiS3Error::RpcError(message) => write!(f, "Cannot reach the sui rpc: {}", message),
+            SuiS3Error::TransactionFailed(error) => write!(f, "The transaction failed on chain: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for SuiS3Error {}
+
+// abort codes of the suis3 contract
+const ENO_SUCH_BUCKET: u64 = 1;
+const ENO_SUCH_OBJECT: u64 = 4;
+
+// an executed transaction may still have failed, a missing bucket or object aborts the move call
+fn check_execution(response: &SuiTransactionBlockResponse, bucket_name: &str, obj_name: &str) -> Result<(), SuiS3Error> {
+    let Some(SuiExecutionStatus::Failure { error }) = response.effects.as_ref().map(|e| e.status()) else {
+        return Ok(());
+    };
+    // e.g. MoveAbort(MoveLocation { .. function_name: Some("get_object") }, 4) in command 0
+    let code = Regex::new(r"MoveAbort\(.*\}, (\d+)\)")
+        .unwrap()
+        .captures(error)
+        .and_then(|caps| caps[1].parse::<u64>().ok());
+    Err(match code {
+        Some(ENO_SUCH_BUCKET) => SuiS3Error::BucketNotFound(bucket_name.to_owned()),
+        Some(ENO_SUCH_OBJECT) => SuiS3Error::ObjectNotFound(bucket_name.to_owned(), obj_name.to_owned()),
+        _ if error.contains("InsufficientGas") => {
+            SuiS3Error::InsufficientGas(response.transaction.as_ref().map(|t| t.data.sender().to_string()).unwrap_or_default())
+        }
+        _ => SuiS3Error::TransactionFailed(error.clone()),
+    })
+}
 
 #[serde_as]
 #[derive(Deserialize, Debug)]
@@ -45,7 +135,7 @@ pub struct TagsList {
 }
 
 #[serde_as]
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 pub struct BucketObjectsInfo {
     pub uri: String,
     #[serde_as(as = "DisplayFromStr")]
@@ -58,16 +148,335 @@ pub struct BucketObjectsInfo {
     pub walrus_epoch_till: u64,
 }
 
+impl BucketObjectsInfo {
+    pub fn to_blob_meta(&self) -> walrus::BlobMeta {
+        walrus::BlobMeta {
+            size: self.size,
+            tags: self.tags.clone(),
+            last_write_ts: self.last_write_ts,
+            walrus_blob_id: self.walrus_blob_id.clone(),
+            walrus_epoch_till: self.walrus_epoch_till,
+        }
+    }
+}
+
+// tag holding the mime type of the object, set by put
+pub const CONTENT_TYPE_TAG: &str = "content-type";
+
+// tag naming the compression put applied to the blob, get undoes it
+pub const CONTENT_ENCODING_TAG: &str = "content-encoding";
+
+// tag recording the write time of the source of cp --preserve and mv, the copy gets a new one on chain
+pub const ORIGINAL_WRITE_TS_TAG: &str = "original-write-ts";
+
+// tag of the objects `del` refuses to delete, set by lock. only the cli honours it, the contract does not
+pub const LOCKED_TAG: &str = "locked";
+
+// tag of an object put with --versioned, the number of its latest version.
+// the object itself holds version 1, version n > 1 is the object <key>.v<n> of the same bucket
+pub const LATEST_VERSION_TAG: &str = "latest-version";
+
+pub fn version_key(obj_name: &str, version: u64) -> String {
+    match version {
+        1 => obj_name.to_owned(),
+        _ => format!("{}.v{}", obj_name, version),
+    }
+}
+
+// 1 for an object never put with --versioned
+pub fn latest_version(tags: &[String]) -> u64 {
+    tag_value(tags, LATEST_VERSION_TAG).and_then(|v| v.parse().ok()).unwrap_or(1)
+}
+
+// the key and metadata of the version of the object, the latest one without a version
+pub async fn resolve_version(bucket_name: &str, obj_name: &str, version: Option<u64>) -> Result<(String, walrus::BlobMeta), anyhow::Error> {
+    let meta = get_object(bucket_name.to_owned(), obj_name.to_owned()).await?;
+    let latest = latest_version(&meta.tags);
+    let version = version.unwrap_or(latest);
+    if version == 1 {
+        return Ok((obj_name.to_owned(), meta));
+    }
+    let key = version_key(obj_name, version);
+    if version == 0 || version > latest {
+        return Err(SuiS3Error::ObjectNotFound(bucket_name.to_owned(), key).into());
+    }
+    let meta = get_object(bucket_name.to_owned(), key.clone()).await?;
+    Ok((key, meta))
+}
+
+pub fn is_locked(tags: &[String]) -> bool {
+    tag_value(tags, LOCKED_TAG) == Some("true")
+}
+
+// every command replacing, moving or deleting an existing object checks it here: a locked object
+// is left alone unless the command has --force-unlock and it was given
+pub fn ensure_unlocked(bucket_name: &str, obj_name: &str, tags: &[String], force_unlock: bool) -> Result<(), anyhow::Error> {
+    if is_locked(tags) && !force_unlock {
+        return Err(anyhow!("suis3://{}{} is locked, unlock it first", bucket_name, obj_name));
+    }
+    Ok(())
+}
+
+// the value of a key=value tag
+pub fn tag_value<'a>(tags: &'a [String], key: &str) -> Option<&'a str> {
+    tags.iter().find_map(|tag| match tag.split_once('=') {
+        Some((k, v)) if k.trim() == key => Some(v.trim()),
+        _ => None,
+    })
+}
+
+// the tags of `base` whose key is not among `overrides`, followed by `overrides`
+pub fn merge_tags(base: &[String], overrides: &[String]) -> Vec<String> {
+    let key = |tag: &String| tag.split('=').next().unwrap().trim().to_owned();
+    let mut tags = base
+        .iter()
+        .filter(|tag| !overrides.iter().any(|o| key(o) == key(tag)))
+        .cloned()
+        .collect::<Vec<_>>();
+    tags.extend(overrides.iter().cloned());
+    tags
+}
+
+// the key of a key=value tag
+pub fn tag_key(tag: &str) -> &str {
+    tag.split('=').next().unwrap().trim()
+}
+
+// the tags tag add and tag del carry over, the contract replaces the whole tag vector of an object.
+// locked is only changed by lock and unlock, the content tags and latest-version by put
+pub const PROTECTED_TAGS: [&str; 4] = [LOCKED_TAG, CONTENT_TYPE_TAG, CONTENT_ENCODING_TAG, LATEST_VERSION_TAG];
+
+pub fn protected_tags(tags: &[String]) -> Vec<String> {
+    tags.iter().filter(|tag| PROTECTED_TAGS.contains(&tag_key(tag))).cloned().collect()
+}
+
+// the tags of an object after tag add: the added ones win over the others, the protected ones stay as they are
+pub fn add_tags(current: &[String], added: &[String]) -> Vec<String> {
+    let added = added.iter().filter(|tag| !PROTECTED_TAGS.contains(&tag_key(tag))).cloned().collect::<Vec<_>>();
+    merge_tags(current, &added)
+}
+
 #[derive(Deserialize, Debug)]
 pub struct BucketObjectsList {
     pub objects: Vec<BucketObjectsInfo>,
 }
 
+// bump when the layout of the export records changes
+pub const EXPORT_VERSION: u32 = 1;
+
+// one line of a bucket export, the object fields are kept as emitted on chain
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ExportRecord {
+    pub version: u32,
+    pub bucket: String,
+    #[serde(flatten)]
+    pub object: BucketObjectsInfo,
+}
+
+impl BucketObjectsList {
+    // keep at most `limit` objects after the `start_after` key, in key order.
+    // returns true when more objects remain beyond the page
+    pub fn paginate(&mut self, start_after: Option<&str>, limit: Option<usize>) -> bool {
+        self.objects.sort_by(|a, b| a.uri.cmp(&b.uri));
+
+        if let Some(key) = start_after {
+            let key = if key.starts_with('/') { key.to_owned() } else { "/".to_owned() + key };
+            self.objects.retain(|o| o.uri > key);
+        }
+
+        match limit {
+            Some(n) if self.objects.len() > n => {
+                self.objects.truncate(n);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // keep the objects written from `after` and before `before`, both in milliseconds
+    pub fn filter_time(&mut self, after: Option<u64>, before: Option<u64>) {
+        self.objects.retain(|o| {
+            after.map_or(true, |t| o.last_write_ts >= t) && before.map_or(true, |t| o.last_write_ts < t)
+        });
+    }
+
+    // keep the objects matching all the `key=value` or `key` filters, or any of them
+    pub fn filter_tags(&mut self, filters: &[String], any: bool) {
+        if filters.is_empty() {
+            return;
+        }
+        let matches = |tags: &[String], filter: &String| match filter.split_once('=') {
+            Some((key, value)) => tag_value(tags, key.trim()) == Some(value.trim()),
+            None => tags.iter().any(|tag| tag.split('=').next().unwrap().trim() == filter.trim()),
+        };
+        self.objects.retain(|o| match any {
+            true => filters.iter().any(|f| matches(&o.tags, f)),
+            false => filters.iter().all(|f| matches(&o.tags, f)),
+        });
+    }
+}
+
+// object metadata already fetched by the running command, keyed by (bucket, object)
+pub(crate) type ObjectCache = Arc<Mutex<HashMap<(String, String), walrus::BlobMeta>>>;
+
+// gas budgets estimated by --budget-from-dryrun in the running command, keyed by the move calls and the number
+// of inputs of the transaction, with the bytes of pure input they were estimated for
+pub(crate) type BudgetCache = Arc<Mutex<HashMap<String, (u64, usize)>>>;
+
+static OBJECT_CACHE: OnceLock<ObjectCache> = OnceLock::new();
+static BUDGET_CACHE: OnceLock<BudgetCache> = OnceLock::new();
+
+// a library client has its own caches for each call, the cli shares these between the commands of a shell
+fn object_cache() -> ObjectCache {
+    if let Some(context) = crate::client::context() {
+        return context.objects.clone();
+    }
+    OBJECT_CACHE.get_or_init(ObjectCache::default).clone()
+}
+
+fn budget_cache() -> BudgetCache {
+    if let Some(context) = crate::client::context() {
+        return context.budgets.clone();
+    }
+    BUDGET_CACHE.get_or_init(BudgetCache::default).clone()
+}
+
+// called before each command, so a shell session never sees stale metadata nor gas estimates
+pub fn clear_object_cache() {
+    object_cache().lock().unwrap().clear();
+    budget_cache().lock().unwrap().clear();
+}
+
+fn forget_object(bucket_name: &str, obj_name: &str) {
+    object_cache().lock().unwrap().remove(&(bucket_name.to_owned(), obj_name.to_owned()));
+}
+
+// write transactions may skip waiting for local execution with --no-wait
 async fn commit_transaction(pt: sui_types::transaction::ProgrammableTransaction) -> Result<sui_json_rpc_types::SuiTransactionBlockResponse, anyhow::Error> {
-    let (sui, sender, _recipient, coin) = utils::setup_for_write().await?;
+    let request_type = if settings::get().no_wait {
+        ExecuteTransactionRequestType::WaitForEffectsCert
+    } else {
+        ExecuteTransactionRequestType::WaitForLocalExecution
+    };
+    let start = Instant::now();
+    let response = execute_transaction(pt, request_type, true).await;
+    log::info!("commit_transaction took {:.2?}", start.elapsed());
+    response
+}
+
+// the first event which parses as T, the contract may emit others around it
+fn parse_event<T: DeserializeOwned>(response: &SuiTransactionBlockResponse) -> Result<T, anyhow::Error> {
+    let events = response.events.as_ref().map(|e| e.data.as_slice()).unwrap_or(&[]);
+    if events.len() == 0 {
+        return Err(anyhow!("Nothing returned. Your command may be incorrect."));
+    }
+    let mut errors = Vec::new();
+    for event in events.iter() {
+        match serde_json::from_value::<T>(event.parsed_json.clone()) {
+            Ok(ret) => return Ok(ret),
+            Err(e) => errors.push(format!("{} ({})", event.parsed_json, e)),
+        }
+    }
+    Err(anyhow!("No event has the expected {}: {}", std::any::type_name::<T>(), errors.join(", ")))
+}
+
+// read transactions always wait, their results are carried by the emitted events.
+// at debug level (--dump-events) the events are printed before anything parses them
+async fn query_transaction(pt: sui_types::transaction::ProgrammableTransaction) -> Result<sui_json_rpc_types::SuiTransactionBlockResponse, anyhow::Error> {
+    let response = execute_transaction(pt, ExecuteTransactionRequestType::WaitForLocalExecution, false).await?;
+    if log::log_enabled!(log::Level::Debug) {
+        let events = response.events.as_ref().map(|e| e.data.as_slice()).unwrap_or(&[]);
+        log::debug!("Transaction {} emitted {} event(s)", response.digest, events.len());
+        for (i, event) in events.iter().enumerate() {
+            log::debug!("Event {} {}: {}", i, event.type_, event.parsed_json);
+        }
+    }
+    Ok(response)
+}
+
+// the gas of a dry run plus the --budget-margin percent, the computation and storage are charged before the rebate.
+// the transactions with the same move calls and inputs which follow in the command, e.g. the batches of put -r,
+// reuse it unless they carry more bytes, the storage grows with the names and tags written.
+// a failed dry run leaves the configured budget, the real execution then reports the failure
+async fn estimate_budget(sui: &sui_sdk::SuiClient, sender: sui_types::base_types::SuiAddress, coin: &sui_json_rpc_types::Coin,
+                         pt: &sui_types::transaction::ProgrammableTransaction, gas_price: u64) -> Result<u64, anyhow::Error> {
+    let calls = pt
+        .commands
+        .iter()
+        .map(|command| match command {
+            Command::MoveCall(call) => format!("{}::{}", call.module, call.function),
+            _ => "-".to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    let key = format!("{}/{}", calls, pt.inputs.len());
+    let payload = pt
+        .inputs
+        .iter()
+        .map(|input| match input {
+            CallArg::Pure(bytes) => bytes.len(),
+            _ => 0,
+        })
+        .sum::<usize>();
+    if let Some((budget, estimated)) = budget_cache().lock().unwrap().get(&key) {
+        if payload <= *estimated {
+            return Ok(*budget);
+        }
+    }
+
+    let gas_budget = settings::get().config.gas_budget;
+    let tx_data = TransactionData::new_programmable(sender, vec![coin.object_ref()], pt.clone(), gas_budget, gas_price);
+    let response = sui.read_api().dry_run_transaction_block(tx_data).await?;
+    if !response.effects.status().is_ok() {
+        log::warn!("The dry run failed, using the gas budget of {} MIST: {:?}", gas_budget, response.effects.status());
+        return Ok(gas_budget);
+    }
+    let gas = response.effects.gas_cost_summary();
+    let cost = gas.computation_cost + gas.storage_cost;
+    let budget = cost + cost * settings::get().budget_margin / 100;
+    log::info!("Dry run cost {} MIST, budget {} MIST", cost, budget);
+    budget_cache().lock().unwrap().insert(key, (budget, payload));
+    Ok(budget)
+}
+
+// a balance in MIST as SUI
+fn display_sui(mist: u128) -> String {
+    format!("{}.{:09} SUI", mist / 1_000_000_000, mist % 1_000_000_000)
+}
+
+// after a write, the gas it used and what the sender has left: on stderr with --show-balance, in the --verbose output otherwise
+async fn report_balance(sui: &sui_sdk::SuiClient, sender: sui_types::base_types::SuiAddress, response: &SuiTransactionBlockResponse) {
+    let show_balance = settings::get().show_balance;
+    if !show_balance && !log::log_enabled!(log::Level::Info) {
+        return;
+    }
+    let used = response.effects.as_ref().map(|effects| effects.gas_cost_summary().net_gas_usage());
+    let line = match sui.coin_read_api().get_balance(sender, None).await {
+        Ok(balance) => format!("Gas used {} MIST, {} left on {}", used.unwrap_or(0), display_sui(balance.total_balance), sender),
+        Err(e) => format!("Gas used {} MIST, cannot read the balance of {}: {}", used.unwrap_or(0), sender, e),
+    };
+    match show_balance {
+        true => eprintln!("{}", line),
+        false => log::info!("{}", line),
+    }
+}
+
+// a write is budgeted by its dry run with --budget-from-dryrun, and followed by the balance report
+async fn execute_transaction(pt: sui_types::transaction::ProgrammableTransaction, request_type: ExecuteTransactionRequestType,
+                             write: bool) -> Result<sui_json_rpc_types::SuiTransactionBlockResponse, anyhow::Error> {
+    let (sui, sender, coin) = utils::setup_for_write().await?;
 
-    let gas_budget = 10_000_000;
     let gas_price = sui.read_api().get_reference_gas_price().await?;
+    let gas_budget = match write && settings::get().budget_from_dryrun {
+        true => estimate_budget(&sui, sender, &coin, &pt, gas_price).await?,
+        false => settings::get().config.gas_budget,
+    };
+    for command in pt.commands.iter() {
+        if let Command::MoveCall(call) = command {
+            log::info!("Calling {}::{}", call.module, call.function);
+        }
+    }
+    log::info!("Sender {}, gas coin {}, budget {}, price {}", sender, coin.coin_object_id, gas_budget, gas_price);
     // create the transaction data that will be sent to the network
     let tx_data = TransactionData::new_programmable(
         sender.clone(),
@@ -78,7 +487,7 @@ async fn commit_transaction(pt: sui_types::transaction::ProgrammableTransaction)
     );
 
     // sign transaction
-    let keystore = FileBasedKeystore::new(&sui_config_dir()?.join(SUI_KEYSTORE_FILENAME))?;
+    let keystore = utils::keystore()?;
     let signature = keystore.sign_secure(&sender, &tx_data, Intent::sui_transaction())?;
 
     // execute the transaction
@@ -87,21 +496,51 @@ async fn commit_transaction(pt: sui_types::transaction::ProgrammableTransaction)
         .execute_transaction_block(
             Transaction::from_data(tx_data, vec![signature]),
             SuiTransactionBlockResponseOptions::full_content(),
-            Some(ExecuteTransactionRequestType::WaitForLocalExecution),
+            Some(request_type),
         )
         .await?;
 
+    if let Some(effects) = transaction_response.effects.as_ref() {
+        let gas = effects.gas_cost_summary();
+        log::info!(
+            "Transaction {}: computation {}, storage {}, rebate {}, total {} MIST",
+            transaction_response.digest,
+            gas.computation_cost,
+            gas.storage_cost,
+            gas.storage_rebate,
+            gas.net_gas_usage()
+        );
+    }
+    if write {
+        report_balance(&sui, sender, &transaction_response).await;
+    }
     Ok(transaction_response)
 }
 
-pub async fn create_bucket(name: String) -> Result<(), anyhow::Error> {
+// the buckets root object as the first input of a transaction, a failed or empty read is an rpc error
+async fn buckets_root_arg(sui_client: &sui_sdk::SuiClient) -> Result<CallArg, SuiS3Error> {
+    let object_id: ObjectID = settings::get()
+        .config
+        .buckets_root
+        .parse()
+        .map_err(|e| SuiS3Error::RpcError(format!("invalid buckets root: {}", e)))?;
+    let response = sui_client
+        .read_api()
+        .get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless())
+        .await
+        .map_err(|e| SuiS3Error::RpcError(e.to_string()))?;
+    let obj = response
+        .data
+        .ok_or(SuiS3Error::RpcError(format!("cannot read the buckets root {}", object_id)))?;
+    Ok(CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest))))
+}
+
+pub async fn create_bucket(name: String, tags: Vec<String>) -> Result<TransactionDigest, anyhow::Error> {
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
     // clock
@@ -115,12 +554,11 @@ pub async fn create_bucket(name: String) -> Result<(), anyhow::Error> {
     let arg2 = CallArg::Pure(bcs::to_bytes(&name).unwrap());
     ptb.input(arg2)?;
 
-    let tag = "".to_owned();
-    let arg3 = CallArg::Pure(bcs::to_bytes(&tag).unwrap());
+    let arg3 = CallArg::Pure(bcs::to_bytes(&tags).unwrap());
     ptb.input(arg3)?;
 
     // add a move call to the PTB
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("create_bucket").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -133,22 +571,21 @@ pub async fn create_bucket(name: String) -> Result<(), anyhow::Error> {
 
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
-    commit_transaction(builder).await?;
+    let transaction_response = commit_transaction(builder).await?;
+    check_execution(&transaction_response, &name, "")?;
 
-    Ok(())
+    Ok(transaction_response.digest)
 }
 
 pub async fn list_buckets() -> Result<Vec<BucketInfo>, anyhow::Error> {
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("ls_buckets").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -162,32 +599,78 @@ pub async fn list_buckets() -> Result<Vec<BucketInfo>, anyhow::Error> {
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
 
-    let transaction_response = commit_transaction(builder).await?;
+    let transaction_response = query_transaction(builder).await?;
 
-    let v = &transaction_response.events.unwrap().data;
-    if v.len() <= 0 {
-        return Err(anyhow!("Nothing returned. Your command may be incorrect."));
-    }
-    let ret: BucketsList = serde_json::from_str(v[0].parsed_json.to_string().as_str())?;
+    let ret: BucketsList = parse_event(&transaction_response)?;
 
     Ok(ret.buckets)    
 }
 
-pub async fn delete_bucket(name: String) -> Result<(), anyhow::Error> {
+// the tags of several buckets in one transaction, one get_bucket_tags call per bucket.
+// the events come in the order of the calls
+pub async fn list_buckets_tags(names: &[String]) -> Result<Vec<Vec<String>>, anyhow::Error> {
+    if names.len() == 0 {
+        return Ok(Vec::new());
+    }
+
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let root = ptb.input(buckets_root_arg(&sui_client).await?)?;
+
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
+    for name in names.iter() {
+        let arguments = vec![root, ptb.input(CallArg::Pure(bcs::to_bytes(name).unwrap()))?];
+        ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
+            package,
+            module: Identifier::new("suis3").map_err(|e| anyhow!(e))?,
+            function: Identifier::new("get_bucket_tags").map_err(|e| anyhow!(e))?,
+            type_arguments: vec![],
+            arguments,
+        })));
+    }
+
+    // build the transaction block by calling finish on the ptb
+    let builder = ptb.finish();
+    let transaction_response = query_transaction(builder).await?;
+
+    let events = transaction_response.events.ok_or(anyhow!("Nothing returned. Your command may be incorrect."))?;
+    let v = &events.data;
+    if v.len() != names.len() {
+        return Err(anyhow!("Expected the tags of {} buckets, got {}", names.len(), v.len()));
+    }
+    let mut tags = Vec::new();
+    for event in v.iter() {
+        let ret: TagsList = serde_json::from_value(event.parsed_json.clone())
+            .map_err(|e| anyhow!("Unexpected event {}: {}", event.parsed_json, e))?;
+        tags.push(ret.tags);
+    }
+
+    Ok(tags)
+}
+
+pub async fn bucket_exists(name: &str) -> Result<bool, anyhow::Error> {
+    let buckets = list_buckets().await?;
+
+    Ok(buckets.iter().any(|b| b.name == name))
+}
+
+pub async fn delete_bucket(name: String) -> Result<TransactionDigest, anyhow::Error> {
+    object_cache().lock().unwrap().retain(|(bucket, _), _| bucket != &name);
+
+    let mut ptb = ProgrammableTransactionBuilder::new();
+
+    // bucketsRoot
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
     let arg1 = CallArg::Pure(bcs::to_bytes(&name).unwrap());
     ptb.input(arg1)?;
 
     // add a move call to the PTB
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("delete_bucket").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -200,19 +683,18 @@ pub async fn delete_bucket(name: String) -> Result<(), anyhow::Error> {
 
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
-    commit_transaction(builder).await?;
+    let transaction_response = commit_transaction(builder).await?;
+    check_execution(&transaction_response, &name, "")?;
 
-    Ok(())
+    Ok(transaction_response.digest)
 }
 
-pub async fn tag_bucket(name: String, tags: Vec<String>) -> Result<(), anyhow::Error> {
+pub async fn tag_bucket(name: String, tags: Vec<String>) -> Result<TransactionDigest, anyhow::Error> {
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
     let arg1 = CallArg::Pure(bcs::to_bytes(&name).unwrap());
@@ -222,7 +704,7 @@ pub async fn tag_bucket(name: String, tags: Vec<String>) -> Result<(), anyhow::E
     ptb.input(arg2)?;
 
     // add a move call to the PTB
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("tag_bucket").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -235,26 +717,25 @@ pub async fn tag_bucket(name: String, tags: Vec<String>) -> Result<(), anyhow::E
 
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
-    commit_transaction(builder).await?;
+    let transaction_response = commit_transaction(builder).await?;
+    check_execution(&transaction_response, &name, "")?;
 
-    Ok(())
+    Ok(transaction_response.digest)
 }
 
 pub async fn list_bucket_tags(name: String) -> Result<Vec<String>, anyhow::Error> {
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
     let arg1 = CallArg::Pure(bcs::to_bytes(&name).unwrap());
     ptb.input(arg1)?;
 
     // add a move call to the PTB
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("get_bucket_tags").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -267,32 +748,27 @@ pub async fn list_bucket_tags(name: String) -> Result<Vec<String>, anyhow::Error
 
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
-    let transaction_response = commit_transaction(builder).await?;
+    let transaction_response = query_transaction(builder).await?;
+    check_execution(&transaction_response, &name, "")?;
 
-    let v = &transaction_response.events.unwrap().data;
-    if v.len() <= 0 {
-        return Err(anyhow!("Nothing returned. Your command may be incorrect."));
-    }
-    let ret: TagsList = serde_json::from_str(v[0].parsed_json.to_string().as_str())?;
+    let ret: TagsList = parse_event(&transaction_response)?;
 
     Ok(ret.tags)
 }
 
-pub async fn delete_bucket_tags(name: String) -> Result<(), anyhow::Error> {
+pub async fn delete_bucket_tags(name: String) -> Result<TransactionDigest, anyhow::Error> {
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
     let arg1 = CallArg::Pure(bcs::to_bytes(&name).unwrap());
     ptb.input(arg1)?;
 
     // add a move call to the PTB
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("delete_bucket_tags").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -305,23 +781,144 @@ pub async fn delete_bucket_tags(name: String) -> Result<(), anyhow::Error> {
 
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
-    commit_transaction(builder).await?;
+    let transaction_response = commit_transaction(builder).await?;
+    check_execution(&transaction_response, &name, "")?;
+
+    Ok(transaction_response.digest)
+}
+
+// upload the file and create its metadata, with the given key=value tags
+// ~/.suis3/pending/<hash> keeps the blob of a put between the walrus upload and the metadata save,
+// the hash covers the network, the object and the file content
+fn pending_path(bucket_name: &str, obj_name: &str, filename: &String) -> Result<PathBuf, anyhow::Error> {
+    let mut context = md5::Context::new();
+    for part in [settings::get().config.network.as_str(), bucket_name, obj_name] {
+        context.consume(part.as_bytes());
+        context.consume([0u8]);
+    }
+    consume_file(&mut context, filename)?;
+    let home = dirs::home_dir().ok_or(anyhow!("Cannot find the home directory"))?;
+    Ok(home.join(".suis3").join("pending").join(format!("{:x}", context.compute())))
+}
+
+fn consume_file(context: &mut md5::Context, filename: &String) -> Result<(), anyhow::Error> {
+    let mut file = fs::File::open(filename)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            return Ok(());
+        }
+        context.consume(&buf[..n]);
+    }
+}
+
+// ~/.suis3/blobs lists the files put with --dedup, one `<network>\t<md5>\t<blob meta json>` line each
+fn blob_index_path() -> Result<PathBuf, anyhow::Error> {
+    let home = dirs::home_dir().ok_or(anyhow!("Cannot find the home directory"))?;
+    Ok(home.join(".suis3").join("blobs"))
+}
 
+fn file_md5(filename: &String) -> Result<String, anyhow::Error> {
+    let mut context = md5::Context::new();
+    consume_file(&mut context, filename)?;
+    Ok(format!("{:x}", context.compute()))
+}
+
+// the blob of the same content put with --dedup before, if walrus still stores it.
+// the object then expires with that blob
+pub fn find_indexed_blob(filename: &String) -> Option<walrus::BlobMeta> {
+    let hash = file_md5(filename).ok()?;
+    let network = settings::get().config.network;
+    let content = fs::read_to_string(blob_index_path().ok()?).ok()?;
+    let meta = content.lines().rev().find_map(|line| {
+        let mut fields = line.splitn(3, '\t');
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(n), Some(h), Some(json)) if n == network && h == hash => serde_json::from_str::<walrus::BlobMeta>(json).ok(),
+            _ => None,
+        }
+    })?;
+    let epoch_till = walrus::backend().blob_status(&meta.walrus_blob_id).ok()?;
+    Some(walrus::BlobMeta {
+        tags: Vec::new(),
+        walrus_epoch_till: epoch_till,
+        ..meta
+    })
+}
+
+pub fn index_blob(filename: &String, meta: &walrus::BlobMeta) -> Result<(), anyhow::Error> {
+    let path = blob_index_path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let indexed = walrus::BlobMeta {
+        tags: Vec::new(),
+        ..meta.clone()
+    };
+    let line = format!("{}\t{}\t{}\n", settings::get().config.network, file_md5(filename)?, serde_json::to_string(&indexed)?);
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    file.write_all(line.as_bytes())?;
     Ok(())
 }
 
-pub async fn put_object(bucket_name: &str, obj_name: &str, filename: &String) -> Result<walrus::BlobMeta, anyhow::Error> {
-    // upload to walrus
-    let meta = walrus::walrus_upload_file(filename)?;
+// the blob of an interrupted put, if walrus still stores it
+fn read_pending(path: &PathBuf) -> Option<walrus::BlobMeta> {
+    let content = fs::read_to_string(path).ok()?;
+    let meta = serde_json::from_str::<walrus::BlobMeta>(&content).ok();
+    let stored = meta.filter(|meta| walrus::backend().blob_status(&meta.walrus_blob_id).is_ok());
+    if stored.is_none() {
+        let _ = fs::remove_file(path);
+    }
+    stored
+}
+
+fn write_pending(path: &PathBuf, meta: &walrus::BlobMeta) -> Result<(), anyhow::Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string(meta)?)?;
+    Ok(())
+}
+
+pub async fn put_object(bucket_name: &str, obj_name: &str, filename: &String, tags: Vec<String>) -> Result<(walrus::BlobMeta, TransactionDigest), anyhow::Error> {
+    let pending = pending_path(bucket_name, obj_name, filename)?;
+    let mut meta = match read_pending(&pending) {
+        Some(meta) => {
+            log::info!("Reusing blob {} uploaded by an interrupted put", meta.walrus_blob_id);
+            meta
+        }
+        None => {
+            // upload to walrus
+            let start = Instant::now();
+            let meta = walrus::backend().upload(filename)?;
+            log::info!("Walrus upload of {} bytes took {:.2?}", meta.size, start.elapsed());
+            // a failed record only costs the reuse
+            if let Err(e) = write_pending(&pending, &meta) {
+                log::warn!("Cannot record the pending blob in {:?}: {}", pending, e);
+            }
+            meta
+        }
+    };
+    meta.tags = tags;
     
     // save meta data to contract
+    let start = Instant::now();
+    let digest = create_object(bucket_name, obj_name, &meta).await?;
+    log::info!("Saving the metadata took {:.2?}", start.elapsed());
+    let _ = fs::remove_file(&pending);
+
+    Ok((meta, digest))
+}
+
+// create (or overwrite) the object metadata pointing at an already stored walrus blob
+pub async fn create_object(bucket_name: &str, obj_name: &str, meta: &walrus::BlobMeta) -> Result<TransactionDigest, anyhow::Error> {
+    forget_object(bucket_name, obj_name);
+
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
     // clock
@@ -353,12 +950,11 @@ pub async fn put_object(bucket_name: &str, obj_name: &str, filename: &String) ->
     ptb.input(arg6)?;
     
     // tags
-    let tags = Vec::<String>::new();
-    let arg7 = CallArg::Pure(bcs::to_bytes(&tags).unwrap());
+    let arg7 = CallArg::Pure(bcs::to_bytes(&meta.tags).unwrap());
     ptb.input(arg7)?;
 
     // add a move call to the PTB
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("create_object").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -372,19 +968,149 @@ pub async fn put_object(bucket_name: &str, obj_name: &str, filename: &String) ->
 
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
-    commit_transaction(builder).await?;
+    let transaction_response = commit_transaction(builder).await?;
+    check_execution(&transaction_response, bucket_name, obj_name)?;
 
-    Ok(meta)  
+    Ok(transaction_response.digest)
 } 
 
+// create several objects in one transaction, sharing the buckets root and clock inputs.
+// the transaction is atomic, one invalid object fails the whole batch
+pub async fn create_objects(bucket_name: &str, objects: &[(String, walrus::BlobMeta)]) -> Result<TransactionDigest, anyhow::Error> {
+    for (obj_name, _) in objects.iter() {
+        forget_object(bucket_name, obj_name);
+    }
+
+    let mut ptb = ProgrammableTransactionBuilder::new();
+
+    // bucketsRoot
+    let sui_client = utils::sui_client().await?;
+    let root = ptb.input(buckets_root_arg(&sui_client).await?)?;
+
+    // clock
+    let clock = ptb.input(CallArg::Object(ObjectArg::SharedObject {
+        id: "0x6".parse().unwrap(),
+        initial_shared_version: 1.into(),
+        mutable: false,
+    }))?;
+
+    let bucket = ptb.input(CallArg::Pure(bcs::to_bytes(&bucket_name).unwrap()))?;
+
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
+    for (obj_name, meta) in objects.iter() {
+        let arguments = vec![
+            root,
+            clock,
+            bucket,
+            ptb.input(CallArg::Pure(bcs::to_bytes(obj_name).unwrap()))?,
+            ptb.input(CallArg::Pure(bcs::to_bytes(&meta.size).unwrap()))?,
+            ptb.input(CallArg::Pure(bcs::to_bytes(&meta.walrus_blob_id).unwrap()))?,
+            ptb.input(CallArg::Pure(bcs::to_bytes(&meta.walrus_epoch_till).unwrap()))?,
+            ptb.input(CallArg::Pure(bcs::to_bytes(&meta.tags).unwrap()))?,
+        ];
+        ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
+            package,
+            module: Identifier::new("suis3").map_err(|e| anyhow!(e))?,
+            function: Identifier::new("create_object").map_err(|e| anyhow!(e))?,
+            type_arguments: vec![],
+            arguments,
+        })));
+    }
+
+    // build the transaction block by calling finish on the ptb
+    let builder = ptb.finish();
+    let transaction_response = commit_transaction(builder).await?;
+
+    check_execution(&transaction_response, bucket_name, "").map_err(|e| match e {
+        SuiS3Error::TransactionFailed(error) => anyhow!("Creating {} objects failed on chain: {}", objects.len(), error),
+        e => e.into(),
+    })?;
+
+    Ok(transaction_response.digest)
+}
+
+// rename objects in one transaction: the metadata is created under the new name and deleted under the old one,
+// so either all the objects of the call move or none
+pub async fn move_objects(bucket_name: &str, moves: &[(String, String, walrus::BlobMeta)]) -> Result<TransactionDigest, anyhow::Error> {
+    for (old_name, new_name, _) in moves.iter() {
+        forget_object(bucket_name, old_name);
+        forget_object(bucket_name, new_name);
+    }
+
+    let mut ptb = ProgrammableTransactionBuilder::new();
+
+    // bucketsRoot
+    let sui_client = utils::sui_client().await?;
+    let root = ptb.input(buckets_root_arg(&sui_client).await?)?;
+
+    // clock
+    let clock = ptb.input(CallArg::Object(ObjectArg::SharedObject {
+        id: "0x6".parse().unwrap(),
+        initial_shared_version: 1.into(),
+        mutable: false,
+    }))?;
+
+    let bucket = ptb.input(CallArg::Pure(bcs::to_bytes(&bucket_name).unwrap()))?;
+
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
+    for (old_name, new_name, meta) in moves.iter() {
+        let arguments = vec![
+            root,
+            clock,
+            bucket,
+            ptb.input(CallArg::Pure(bcs::to_bytes(new_name).unwrap()))?,
+            ptb.input(CallArg::Pure(bcs::to_bytes(&meta.size).unwrap()))?,
+            ptb.input(CallArg::Pure(bcs::to_bytes(&meta.walrus_blob_id).unwrap()))?,
+            ptb.input(CallArg::Pure(bcs::to_bytes(&meta.walrus_epoch_till).unwrap()))?,
+            ptb.input(CallArg::Pure(bcs::to_bytes(&meta.tags).unwrap()))?,
+        ];
+        ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
+            package,
+            module: Identifier::new("suis3").map_err(|e| anyhow!(e))?,
+            function: Identifier::new("create_object").map_err(|e| anyhow!(e))?,
+            type_arguments: vec![],
+            arguments,
+        })));
+
+        let arguments = vec![root, bucket, ptb.input(CallArg::Pure(bcs::to_bytes(old_name).unwrap()))?];
+        ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
+            package,
+            module: Identifier::new("suis3").map_err(|e| anyhow!(e))?,
+            function: Identifier::new("delete_object").map_err(|e| anyhow!(e))?,
+            type_arguments: vec![],
+            arguments,
+        })));
+    }
+
+    // build the transaction block by calling finish on the ptb
+    let builder = ptb.finish();
+    let transaction_response = commit_transaction(builder).await?;
+
+    check_execution(&transaction_response, bucket_name, "").map_err(|e| match e {
+        SuiS3Error::TransactionFailed(error) => anyhow!("Moving {} objects failed on chain: {}", moves.len(), error),
+        e => e.into(),
+    })?;
+
+    Ok(transaction_response.digest)
+}
+
 pub async fn get_object_id(bucket_name: String, obj_name: String) -> Result<String, anyhow::Error> {
+    let meta = get_object(bucket_name, obj_name).await?;
+
+    Ok(meta.walrus_blob_id)
+}
+
+pub async fn get_object(bucket_name: String, obj_name: String) -> Result<walrus::BlobMeta, anyhow::Error> {
+    let key = (bucket_name.clone(), obj_name.clone());
+    if let Some(meta) = object_cache().lock().unwrap().get(&key) {
+        return Ok(meta.clone());
+    }
+
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
     // bucket name
@@ -396,7 +1122,7 @@ pub async fn get_object_id(bucket_name: String, obj_name: String) -> Result<Stri
     ptb.input(arg2)?;
 
     // add a move call to the PTB
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("get_object").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -409,26 +1135,23 @@ pub async fn get_object_id(bucket_name: String, obj_name: String) -> Result<Stri
 
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
-    let transaction_response = commit_transaction(builder).await?;
+    let transaction_response = query_transaction(builder).await?;
+    check_execution(&transaction_response, &bucket_name, &obj_name)?;
 
-    let v = &transaction_response.events.unwrap().data;
-    if v.len() <= 0 {
-        return Err(anyhow!("Nothing returned. Your command may be incorrect."));
-    }
-    // println!("{:?}", v);
-    let ret: walrus::BlobMeta = serde_json::from_str(v[0].parsed_json.to_string().as_str())?;
+    let ret: walrus::BlobMeta = parse_event(&transaction_response)?;
+    object_cache().lock().unwrap().insert(key, ret.clone());
 
-    Ok(ret.walrus_blob_id)
+    Ok(ret)
 }
 
-pub async fn delete_object(bucket_name: String, obj_name: String) -> Result<(), anyhow::Error> {
+pub async fn delete_object(bucket_name: String, obj_name: String) -> Result<TransactionDigest, anyhow::Error> {
+    forget_object(&bucket_name, &obj_name);
+
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
     // bucket name
@@ -440,7 +1163,7 @@ pub async fn delete_object(bucket_name: String, obj_name: String) -> Result<(),
     ptb.input(arg2)?;
 
     // add a move call to the PTB
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("delete_object").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -453,19 +1176,20 @@ pub async fn delete_object(bucket_name: String, obj_name: String) -> Result<(),
 
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
-    commit_transaction(builder).await?;
+    let transaction_response = commit_transaction(builder).await?;
+    check_execution(&transaction_response, &bucket_name, &obj_name)?;
 
-    Ok(())
+    Ok(transaction_response.digest)
 }
 
-pub async fn tag_object(bucket_name: String, obj_name: String, tags: Vec<String>) -> Result<(), anyhow::Error> {
+pub async fn tag_object(bucket_name: String, obj_name: String, tags: Vec<String>) -> Result<TransactionDigest, anyhow::Error> {
+    forget_object(&bucket_name, &obj_name);
+
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
     let arg1 = CallArg::Pure(bcs::to_bytes(&bucket_name).unwrap());
@@ -478,7 +1202,7 @@ pub async fn tag_object(bucket_name: String, obj_name: String, tags: Vec<String>
     ptb.input(arg3)?;
 
     // add a move call to the PTB
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("tag_object").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -491,19 +1215,60 @@ pub async fn tag_object(bucket_name: String, obj_name: String, tags: Vec<String>
 
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
-    commit_transaction(builder).await?;
+    let transaction_response = commit_transaction(builder).await?;
+    check_execution(&transaction_response, &bucket_name, &obj_name)?;
 
-    Ok(())
+    Ok(transaction_response.digest)
+}
+
+// set the same tags on several objects of the bucket in one transaction, sharing the buckets root input.
+// the transaction is atomic, one missing object fails the whole batch
+// each object with its whole new tag vector, the contract replaces the tags of an object
+pub async fn tag_objects(bucket_name: &str, objects: &[(String, Vec<String>)]) -> Result<TransactionDigest, anyhow::Error> {
+    for (obj_name, _) in objects.iter() {
+        forget_object(bucket_name, obj_name);
+    }
+
+    let mut ptb = ProgrammableTransactionBuilder::new();
+
+    // bucketsRoot
+    let sui_client = utils::sui_client().await?;
+    let root = ptb.input(buckets_root_arg(&sui_client).await?)?;
+
+    let bucket = ptb.input(CallArg::Pure(bcs::to_bytes(&bucket_name).unwrap()))?;
+
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
+    for (obj_name, tags) in objects.iter() {
+        let name = ptb.input(CallArg::Pure(bcs::to_bytes(obj_name).unwrap()))?;
+        let tags = ptb.input(CallArg::Pure(bcs::to_bytes(tags).unwrap()))?;
+        let arguments = vec![root, bucket, name, tags];
+        ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
+            package,
+            module: Identifier::new("suis3").map_err(|e| anyhow!(e))?,
+            function: Identifier::new("tag_object").map_err(|e| anyhow!(e))?,
+            type_arguments: vec![],
+            arguments,
+        })));
+    }
+
+    // build the transaction block by calling finish on the ptb
+    let builder = ptb.finish();
+    let transaction_response = commit_transaction(builder).await?;
+
+    check_execution(&transaction_response, bucket_name, "").map_err(|e| match e {
+        SuiS3Error::TransactionFailed(error) => anyhow!("Tagging {} objects failed on chain: {}", objects.len(), error),
+        e => e.into(),
+    })?;
+
+    Ok(transaction_response.digest)
 }
 
 pub async fn list_object_tags(bucket_name: String, obj_name: String) -> Result<Vec<String>, anyhow::Error> {
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
     // bucket name
@@ -515,7 +1280,7 @@ pub async fn list_object_tags(bucket_name: String, obj_name: String) -> Result<V
     ptb.input(arg2)?;
     
     // add a move call to the PTB
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("get_object_tags").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -528,26 +1293,23 @@ pub async fn list_object_tags(bucket_name: String, obj_name: String) -> Result<V
 
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
-    let transaction_response = commit_transaction(builder).await?;
+    let transaction_response = query_transaction(builder).await?;
+    check_execution(&transaction_response, &bucket_name, &obj_name)?;
 
-    let v = &transaction_response.events.unwrap().data;
-    if v.len() <= 0 {
-        return Err(anyhow!("Nothing returned. Your command may be incorrect."));
-    }
-    let ret: TagsList = serde_json::from_str(v[0].parsed_json.to_string().as_str())?;
+    let ret: TagsList = parse_event(&transaction_response)?;
 
     Ok(ret.tags)
 }
 
 
-pub async fn delete_object_tags(bucket_name: String, obj_name: String) -> Result<(), anyhow::Error> {
+pub async fn delete_object_tags(bucket_name: String, obj_name: String) -> Result<TransactionDigest, anyhow::Error> {
+    forget_object(&bucket_name, &obj_name);
+
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
     let arg1 = CallArg::Pure(bcs::to_bytes(&bucket_name).unwrap());
@@ -557,7 +1319,7 @@ pub async fn delete_object_tags(bucket_name: String, obj_name: String) -> Result
     ptb.input(arg2)?;
 
     // add a move call to the PTB
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("delete_object_tags").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -570,19 +1332,18 @@ pub async fn delete_object_tags(bucket_name: String, obj_name: String) -> Result
 
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
-    commit_transaction(builder).await?;
+    let transaction_response = commit_transaction(builder).await?;
+    check_execution(&transaction_response, &bucket_name, &obj_name)?;
 
-    Ok(())
+    Ok(transaction_response.digest)
 }
 
 pub async fn get_bucket_objects(bucket_name: String) -> Result<BucketObjectsList, anyhow::Error> {
     let mut ptb = ProgrammableTransactionBuilder::new();
 
     // bucketsRoot
-    let sui_client = sui_sdk::SuiClientBuilder::default().build_testnet().await.unwrap();
-    let object_id: ObjectID = BUCKETS_ROOT.parse().unwrap();
-    let obj = sui_client.read_api().get_object_with_options(object_id, SuiObjectDataOptions::bcs_lossless()).await.unwrap().data.unwrap();
-    let arg0 = CallArg::Object(ObjectArg::ImmOrOwnedObject((obj.object_id, obj.version, obj.digest)));
+    let sui_client = utils::sui_client().await?;
+    let arg0 = buckets_root_arg(&sui_client).await?;
     ptb.input(arg0)?;
 
     // bucket name
@@ -590,7 +1351,7 @@ pub async fn get_bucket_objects(bucket_name: String) -> Result<BucketObjectsList
     ptb.input(arg1)?;
 
     // add a move call to the PTB
-    let package = ObjectID::from_hex_literal(PACKAGE_ID).map_err(|e| anyhow!(e))?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
     let module = Identifier::new("suis3").map_err(|e| anyhow!(e))?;
     let function = Identifier::new("ls_bucket_objects").map_err(|e| anyhow!(e))?;
     ptb.command(Command::MoveCall(Box::new(ProgrammableMoveCall {
@@ -603,14 +1364,143 @@ pub async fn get_bucket_objects(bucket_name: String) -> Result<BucketObjectsList
 
     // build the transaction block by calling finish on the ptb
     let builder = ptb.finish();
-    let transaction_response = commit_transaction(builder).await?;
+    let transaction_response = query_transaction(builder).await?;
 
-    let v = &transaction_response.events.unwrap().data;
-    if v.len() <= 0 {
-        return Err(anyhow!("Nothing returned. Your command may be incorrect."));
-    }
-    // println!("{:?}", v[0].parsed_json.to_string().as_str());
-    let ret: BucketObjectsList = serde_json::from_str(v[0].parsed_json.to_string().as_str())?;
+    // a huge bucket can exceed the event size limit, the listing fails as a whole rather than truncated
+    check_execution(&transaction_response, &bucket_name, "").map_err(|e| match e {
+        SuiS3Error::TransactionFailed(error) => anyhow!("Listing the bucket failed on chain: {}", error),
+        e => e.into(),
+    })?;
+
+    let ret: BucketObjectsList = parse_event(&transaction_response)?;
 
     Ok(ret)
-}
\ No newline at end of file
+}
+
+#[derive(Debug)]
+pub struct HistoryEntry {
+    pub timestamp_ms: Option<u64>,
+    pub digest: TransactionDigest,
+    pub operation: String,
+    pub object: Option<String>,
+}
+
+// the transactions of the package history reads at most by default, a quiet bucket of a busy package
+// would otherwise page through the whole chain
+pub const HISTORY_SCAN: usize = 1000;
+
+#[derive(Debug)]
+pub struct BucketHistory {
+    pub entries: Vec<HistoryEntry>,
+    // the transactions read, and whether older ones were left unread once `scan` was reached
+    pub scanned: usize,
+    pub stopped_early: bool,
+}
+
+// the write operations only mutate the buckets root without emitting events,
+// so the trail is rebuilt from the transactions calling the package, the newest `scan` of them
+pub async fn get_bucket_history(bucket_name: String, limit: usize, scan: usize) -> Result<BucketHistory, anyhow::Error> {
+    let sui_client = utils::sui_client().await?;
+    let package = ObjectID::from_hex_literal(&settings::get().config.package_id).map_err(|e| anyhow!(e))?;
+
+    let scan = scan.max(1);
+    let mut entries = Vec::<HistoryEntry>::new();
+    let mut cursor = None;
+    let mut scanned = 0;
+    let stopped_early = loop {
+        let query = SuiTransactionBlockResponseQuery::new(
+            Some(TransactionFilter::MoveFunction {
+                package,
+                module: Some("suis3".to_owned()),
+                function: None,
+            }),
+            Some(SuiTransactionBlockResponseOptions::new().with_input().with_effects()),
+        );
+        // newest first, reversed into chronological order below
+        let page_size = (scan - scanned).min(50);
+        let page = sui_client.read_api().query_transaction_blocks(query, cursor, Some(page_size), true).await?;
+        scanned += page.data.len();
+
+        for tx in page.data.iter() {
+            let succeeded = tx.effects.as_ref().map(|e| e.status().is_ok()).unwrap_or(false);
+            if !succeeded {
+                continue;
+            }
+            for (operation, object) in bucket_operations(tx, &package, &bucket_name) {
+                entries.push(HistoryEntry {
+                    timestamp_ms: tx.timestamp_ms,
+                    digest: tx.digest,
+                    operation,
+                    object,
+                });
+            }
+        }
+
+        if entries.len() >= limit || !page.has_next_page {
+            break false;
+        }
+        if scanned >= scan {
+            break true;
+        }
+        cursor = page.next_cursor;
+    };
+
+    entries.truncate(limit);
+    entries.reverse();
+
+    Ok(BucketHistory { entries, scanned, stopped_early })
+}
+
+// (operation, object name) of every write move call on the bucket in the transaction
+fn bucket_operations(tx: &SuiTransactionBlockResponse, package: &ObjectID, bucket_name: &str) -> Vec<(String, Option<String>)> {
+    let mut ops = Vec::new();
+    let Some(block) = &tx.transaction else {
+        return ops;
+    };
+    let SuiTransactionBlockKind::ProgrammableTransaction(pt) = block.data.transaction() else {
+        return ops;
+    };
+
+    for command in pt.commands.iter() {
+        let SuiCommand::MoveCall(call) = command else {
+            continue;
+        };
+        if &call.package != package {
+            continue;
+        }
+        // positions of the bucket and object name arguments, following suis3.move
+        let (bucket_arg, object_arg) = match call.function.as_str() {
+            "create_bucket" => (2, None),
+            "delete_bucket" | "tag_bucket" | "delete_bucket_tags" => (1, None),
+            "create_object" => (2, Some(3)),
+            "delete_object" | "tag_object" | "delete_object_tags" => (1, Some(2)),
+            _ => continue,
+        };
+        if pure_string_arg(pt, &call.arguments, bucket_arg).as_deref() != Some(bucket_name) {
+            continue;
+        }
+        let object = object_arg.and_then(|i| pure_string_arg(pt, &call.arguments, i));
+        ops.push((call.function.clone(), object));
+    }
+
+    ops
+}
+
+fn pure_string_arg(pt: &SuiProgrammableTransactionBlock, arguments: &[SuiArgument], index: usize) -> Option<String> {
+    let SuiArgument::Input(input) = arguments.get(index)? else {
+        return None;
+    };
+    let SuiCallArg::Pure(pure) = pt.inputs.get(*input as usize)? else {
+        return None;
+    };
+
+    // resolved pure values come back as strings, unresolved ones as raw bcs bytes
+    match pure.value().to_json_value() {
+        serde_json::Value::String(s) => Some(s),
+        serde_json::Value::Array(bytes) => {
+            let bytes = bytes.iter().map(|b| b.as_u64().map(|b| b as u8)).collect::<Option<Vec<u8>>>()?;
+            bcs::from_bytes::<String>(&bytes).ok()
+        }
+        _ => None,
+    }
+}
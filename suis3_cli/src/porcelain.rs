@@ -0,0 +1,52 @@
+// the --porcelain layout of la, ls and ll, kept the same across releases for scripts.
+// one record per line ending with \n, the fields separated by a tab, no header.
+// a tab, newline or backslash inside a field is written as \t, \n or \\.
+// times are milliseconds since the unix epoch, tags are separated by a space,
+// a missing value is an empty field. keys keep their leading slash.
+//
+//   la                  <bucket> <created> <tags>
+//   la --stats          <bucket> <created> <tags> <objects> <size>
+//   ls                  <bucket> <created>
+//   ls suis3://<bucket> <key> <written>
+//   ll suis3://<bucket> <key> <written> <size> <content type> <blob id> <till epoch>
+use crate::operations::{self, BucketInfo, BucketObjectsInfo};
+
+pub fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n")
+}
+
+pub fn record(fields: &[String]) -> String {
+    fields.iter().map(|f| escape(f)).collect::<Vec<_>>().join("\t") + "\n"
+}
+
+// a bucket of ls, or of la with its tags
+pub fn bucket(info: &BucketInfo, tags: Option<&[String]>) -> String {
+    let mut fields = vec![info.name.clone(), info.create_ts.to_string()];
+    if let Some(tags) = tags {
+        fields.push(tags.join(" "));
+    }
+    record(&fields)
+}
+
+// a bucket of la --stats, the count and size are empty when the bucket could not be read
+pub fn bucket_stats(info: &BucketInfo, tags: &[String], stats: Option<(usize, u64)>) -> String {
+    let (count, size) = match stats {
+        Some((count, size)) => (count.to_string(), size.to_string()),
+        None => (String::new(), String::new()),
+    };
+    record(&[info.name.clone(), info.create_ts.to_string(), tags.join(" "), count, size])
+}
+
+// an object of ls, or of ll with its detail
+pub fn object(info: &BucketObjectsInfo, detail: bool) -> String {
+    let mut fields = vec![info.uri.clone(), info.last_write_ts.to_string()];
+    if detail {
+        fields.extend([
+            info.size.to_string(),
+            operations::tag_value(&info.tags, operations::CONTENT_TYPE_TAG).unwrap_or("").to_owned(),
+            info.walrus_blob_id.clone(),
+            info.walrus_epoch_till.to_string(),
+        ]);
+    }
+    record(&fields)
+}
@@ -0,0 +1,83 @@
+// the `cat --pretty` view of an object. the kind comes from the content-type tag, else from the
+// extension of the object name, else json is sniffed from the content. json is indented, and
+// json and the languages syntect knows are colored when printed on a terminal.
+// anything else, or content which does not parse, is printed raw.
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::SyntaxSet;
+use syntect::util::{as_24_bit_terminal_escaped, LinesWithEndings};
+
+use crate::utils;
+
+const THEME: &str = "base16-ocean.dark";
+
+// a type which says nothing of the content, the name or the content are looked at instead
+fn is_generic(mime: &str) -> bool {
+    matches!(mime, "" | "application/octet-stream" | "text/plain")
+}
+
+// the file extension syntect knows the type by
+fn extension_of(mime: &str) -> Option<&'static str> {
+    match mime {
+        "application/json" => Some("json"),
+        "text/markdown" => Some("md"),
+        "application/yaml" => Some("yaml"),
+        "application/xml" => Some("xml"),
+        "text/html" => Some("html"),
+        "text/css" => Some("css"),
+        "text/javascript" => Some("js"),
+        _ => None,
+    }
+}
+
+// the extension of the kind of the object, None when it has no kind worth rendering
+fn kind(content: &str, content_type: Option<&str>, name: &str) -> Option<String> {
+    let mime = content_type.and_then(|t| t.split(';').next()).unwrap_or("").trim();
+    if !is_generic(mime) {
+        return extension_of(mime).map(str::to_owned);
+    }
+    if utils::content_type_of(name) != "application/octet-stream" {
+        return std::path::Path::new(name).extension().and_then(|e| e.to_str()).map(str::to_lowercase);
+    }
+    let trimmed = content.trim_start();
+    if (trimmed.starts_with('{') || trimmed.starts_with('['))
+        && serde_json::from_str::<serde_json::Value>(content).is_ok()
+    {
+        return Some("json".to_owned());
+    }
+    None
+}
+
+fn highlight(text: &str, extension: &str) -> Option<String> {
+    let syntaxes = SyntaxSet::load_defaults_newlines();
+    let syntax = syntaxes.find_syntax_by_extension(extension)?;
+    let themes = ThemeSet::load_defaults();
+    let mut lines = HighlightLines::new(syntax, &themes.themes[THEME]);
+    let mut colored = String::new();
+    for line in LinesWithEndings::from(text) {
+        let ranges = lines.highlight_line(line, &syntaxes).ok()?;
+        colored.push_str(&as_24_bit_terminal_escaped(&ranges, false));
+    }
+    // back to the colors of the terminal
+    colored.push_str("\x1b[0m");
+    Some(colored)
+}
+
+// the content as cat --pretty prints it, None to print it raw
+pub fn pretty(content: &[u8], content_type: Option<&str>, name: &str, color: bool) -> Option<String> {
+    let text = std::str::from_utf8(content).ok()?;
+    let extension = kind(text, content_type, name)?;
+    let text = if extension == "json" {
+        let value = serde_json::from_str::<serde_json::Value>(text).ok()?;
+        serde_json::to_string_pretty(&value).ok()?
+    } else {
+        text.to_owned()
+    };
+    if color {
+        if let Some(colored) = highlight(&text, &extension) {
+            return Some(colored);
+        }
+    }
+    // only json changes without colors
+    (extension == "json").then_some(text)
+}
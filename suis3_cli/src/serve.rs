@@ -0,0 +1,242 @@
+// a read-only subset of the S3 api over one bucket, enough for `aws s3 ls/cp --endpoint-url` and browsers:
+// ListBuckets, ListObjectsV2, GetObject and HeadObject in path style, e.g. GET /<bucket>/<key>
+use std::convert::Infallible;
+use std::env;
+use std::fs;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use percent_encoding::percent_decode_str;
+use quick_xml::escape::escape;
+use tokio::net::TcpListener;
+
+use crate::operations;
+use crate::walrus;
+
+// objects listed per ListObjectsV2 page unless max-keys asks for less
+const MAX_KEYS: usize = 1000;
+
+// numbers the temporary files of the downloads served at the same time
+static DOWNLOADS: AtomicU64 = AtomicU64::new(0);
+
+pub async fn serve(bucket: String, port: u16) -> Result<(), anyhow::Error> {
+    if !operations::bucket_exists(&bucket).await? {
+        anyhow::bail!("Bucket suis3://{} does not exist.", bucket);
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+    println!("Serving suis3://{} at http://127.0.0.1:{}/{}, stop with Ctrl-C", bucket, port, bucket);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let bucket = bucket.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req| handle(bucket.clone(), req));
+            if let Err(e) = http1::Builder::new().serve_connection(TokioIo::new(stream), service).await {
+                log::warn!("{}", e);
+            }
+        });
+    }
+}
+
+async fn handle(bucket: String, req: Request<hyper::body::Incoming>) -> Result<Response<Full<Bytes>>, Infallible> {
+    // each request sees the current metadata, like each command of the shell
+    operations::clear_object_cache();
+
+    let head = match *req.method() {
+        Method::GET => false,
+        Method::HEAD => true,
+        _ => return Ok(error(StatusCode::METHOD_NOT_ALLOWED, "MethodNotAllowed", "Only GET and HEAD are served")),
+    };
+
+    // a key with spaces or other characters comes percent-encoded, e.g. /bucket/my%20file.txt
+    let path = percent_decode_str(req.uri().path()).decode_utf8_lossy().trim_start_matches('/').to_owned();
+    let query = req.uri().query().unwrap_or("").to_owned();
+    let (name, key) = match path.split_once('/') {
+        Some((name, key)) => (name.to_owned(), key.to_owned()),
+        None => (path, String::new()),
+    };
+
+    let response = if name.len() == 0 {
+        list_buckets(&bucket)
+    } else if name.to_lowercase() != bucket {
+        error(StatusCode::NOT_FOUND, "NoSuchBucket", "The bucket is not served here")
+    } else if key.len() == 0 {
+        list_objects(&bucket, &query).await
+    } else {
+        get_object(&bucket, &key, head).await
+    };
+    Ok(response)
+}
+
+fn xml(body: String) -> Response<Full<Bytes>> {
+    Response::builder()
+        .header("Content-Type", "application/xml")
+        .body(Full::new(Bytes::from(body)))
+        .unwrap()
+}
+
+fn error(status: StatusCode, code: &str, message: &str) -> Response<Full<Bytes>> {
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Error><Code>{}</Code><Message>{}</Message></Error>",
+        code, escape(message)
+    );
+    let mut response = xml(body);
+    *response.status_mut() = status;
+    response
+}
+
+fn iso_time(ts: u64) -> String {
+    i64::try_from(ts)
+        .ok()
+        .and_then(DateTime::<Utc>::from_timestamp_millis)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn list_buckets(bucket: &str) -> Response<Full<Bytes>> {
+    xml(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <ListAllMyBucketsResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">\
+         <Owner><ID>suis3</ID></Owner><Buckets><Bucket><Name>{}</Name><CreationDate>{}</CreationDate></Bucket></Buckets>\
+         </ListAllMyBucketsResult>",
+        escape(bucket),
+        iso_time(0)
+    ))
+}
+
+async fn list_objects(bucket: &str, query: &str) -> Response<Full<Bytes>> {
+    let mut prefix = String::new();
+    let mut delimiter = String::new();
+    let mut start_after = None;
+    let mut max_keys = MAX_KEYS;
+    for (k, v) in url::form_urlencoded::parse(query.as_bytes()) {
+        match k.as_ref() {
+            "prefix" => prefix = v.into_owned(),
+            "delimiter" => delimiter = v.into_owned(),
+            "start-after" | "continuation-token" => start_after = Some(v.into_owned()),
+            "max-keys" => max_keys = v.parse().unwrap_or(MAX_KEYS).min(MAX_KEYS),
+            _ => {}
+        }
+    }
+
+    let mut list = match operations::get_bucket_objects(bucket.to_owned()).await {
+        Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, "InternalError", &e.to_string()),
+        Ok(list) => list,
+    };
+    list.objects.retain(|o| o.uri.trim_start_matches('/').starts_with(&prefix));
+    list.paginate(start_after.as_deref(), None);
+
+    // max-keys bounds the keys and the common prefixes together, a page ends after the last key of its last prefix
+    let mut contents = String::new();
+    let mut prefixes = Vec::<String>::new();
+    let mut key_count = 0;
+    let mut truncated = false;
+    let mut last = None;
+    for obj in list.objects.iter() {
+        let key = obj.uri.trim_start_matches('/');
+        // keys below a delimiter after the prefix are rolled up like folders
+        let common = match delimiter.len() {
+            0 => None,
+            _ => key[prefix.len()..].find(&delimiter).map(|i| key[..prefix.len() + i + delimiter.len()].to_owned()),
+        };
+        if common.as_ref().is_some_and(|common| prefixes.contains(common)) {
+            last = Some(key);
+            continue;
+        }
+        if key_count == max_keys {
+            truncated = true;
+            break;
+        }
+        key_count += 1;
+        last = Some(key);
+        if let Some(common) = common {
+            prefixes.push(common);
+            continue;
+        }
+        contents += &format!(
+            "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>\"{}\"</ETag><Size>{}</Size>\
+             <StorageClass>STANDARD</StorageClass></Contents>",
+            escape(key),
+            iso_time(obj.last_write_ts),
+            escape(&obj.walrus_blob_id),
+            obj.size
+        );
+    }
+    let common_prefixes = prefixes
+        .iter()
+        .map(|p| format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", escape(p)))
+        .collect::<String>();
+    let next = match (truncated, last) {
+        (true, Some(last)) => format!("<NextContinuationToken>{}</NextContinuationToken>", escape(last)),
+        _ => String::new(),
+    };
+
+    xml(format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">\
+         <Name>{}</Name><Prefix>{}</Prefix><Delimiter>{}</Delimiter><MaxKeys>{}</MaxKeys><KeyCount>{}</KeyCount>\
+         <IsTruncated>{}</IsTruncated>{}{}{}</ListBucketResult>",
+        escape(bucket),
+        escape(&prefix),
+        escape(&delimiter),
+        max_keys,
+        key_count,
+        truncated,
+        next,
+        contents,
+        common_prefixes
+    ))
+}
+
+async fn get_object(bucket: &str, key: &str, head: bool) -> Response<Full<Bytes>> {
+    let meta = match operations::get_object(bucket.to_owned(), format!("/{}", key)).await {
+        Err(_) => return error(StatusCode::NOT_FOUND, "NoSuchKey", "The object does not exist"),
+        Ok(meta) => meta,
+    };
+    let content_type = operations::tag_value(&meta.tags, operations::CONTENT_TYPE_TAG)
+        .unwrap_or("application/octet-stream")
+        .to_owned();
+    let last_modified = i64::try_from(meta.last_write_ts)
+        .ok()
+        .and_then(DateTime::<Utc>::from_timestamp_millis)
+        .unwrap_or_default()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string();
+
+    let body = if head {
+        Bytes::new()
+    } else {
+        let blob_id = meta.walrus_blob_id.clone();
+        let n = DOWNLOADS.fetch_add(1, Ordering::Relaxed);
+        let dest = env::temp_dir().join(format!("suis3_serve_{}_{}", std::process::id(), n)).to_str().unwrap().to_owned();
+        let content = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, anyhow::Error> {
+            walrus::backend().download(&blob_id, &dest)?;
+            let content = fs::read(&dest);
+            let _ = fs::remove_file(&dest);
+            Ok(content?)
+        })
+        .await
+        .map_err(anyhow::Error::from)
+        .and_then(|r| r);
+        match content {
+            Err(e) => return error(StatusCode::BAD_GATEWAY, "InternalError", &e.to_string()),
+            Ok(content) => Bytes::from(content),
+        }
+    };
+
+    let mut response = Response::builder()
+        .header("Content-Type", content_type)
+        .header("Last-Modified", last_modified)
+        .header("ETag", format!("\"{}\"", meta.walrus_blob_id));
+    // the body of GET carries its own length
+    if head {
+        response = response.header("Content-Length", meta.size);
+    }
+    response.body(Full::new(body)).unwrap()
+}
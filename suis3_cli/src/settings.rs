@@ -0,0 +1,335 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{OnceLock, RwLock};
+
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Serialize};
+use sui_types::base_types::{ObjectID, SuiAddress};
+
+pub const DEFAULT_NETWORK: &str = "testnet";
+pub const DEFAULT_PACKAGE_ID: &str = "0xaf4ce64ef7dad2b25ae3dc27165e7f7d238d046206c9a4f78dceea4cce8bd462";
+pub const DEFAULT_BUCKETS_ROOT: &str = "0xe3cf1909b8f9311fbfeb72ffd7f49cb30830abe5f16b7747394f970d6c2711c5";
+pub const DEFAULT_GAS_BUDGET: u64 = 10_000_000;
+pub const DEFAULT_WALRUS_BIN: &str = "walrus";
+pub const DEFAULT_WALRUS_TIMEOUT: u64 = 300;
+pub const DEFAULT_AGGREGATOR_URL: &str = "https://aggregator.walrus-testnet.walrus.space";
+pub const DEFAULT_DOWNLOAD_DIR: &str = ".";
+pub const DEFAULT_CONFIRM_THRESHOLD: u64 = 10;
+
+pub const NETWORKS: [&str; 4] = ["mainnet", "testnet", "devnet", "localnet"];
+pub const CONFIG_KEYS: [&str; 10] = [
+    "network", "rpc_url", "package_id", "buckets_root", "gas_budget", "walrus_bin", "walrus_timeout", "aggregator_url",
+    "download_dir", "confirm_threshold",
+];
+
+pub fn default_rpc_url(network: &str) -> &'static str {
+    match network {
+        "mainnet" => "https://fullnode.mainnet.sui.io:443",
+        "devnet" => "https://fullnode.devnet.sui.io:443",
+        "localnet" => "http://127.0.0.1:9000",
+        _ => "https://fullnode.testnet.sui.io:443",
+    }
+}
+
+// one layer of configuration values, as stored in ~/.suis3/config.toml or given as flags or SUIS3_* variables
+#[derive(Deserialize, Serialize, Clone, Debug, Default)]
+pub struct ConfigValues {
+    pub network: Option<String>,
+    pub rpc_url: Option<String>,
+    pub package_id: Option<String>,
+    pub buckets_root: Option<String>,
+    pub gas_budget: Option<u64>,
+    pub walrus_bin: Option<String>,
+    pub walrus_timeout: Option<u64>,
+    pub aggregator_url: Option<String>,
+    pub download_dir: Option<String>,
+    pub confirm_threshold: Option<u64>,
+}
+
+impl ConfigValues {
+    pub fn path() -> Result<PathBuf, anyhow::Error> {
+        let home = dirs::home_dir().ok_or(anyhow!("Cannot find the home directory"))?;
+        Ok(home.join(".suis3").join("config.toml"))
+    }
+
+    pub fn load() -> Result<ConfigValues, anyhow::Error> {
+        let path = Self::path()?;
+        if !path.exists() {
+            return Ok(ConfigValues::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        toml::from_str(&content).map_err(|e| anyhow!("Invalid config file {:?}: {}", path, e))
+    }
+
+    // SUIS3_<KEY> variables, e.g. SUIS3_NETWORK or SUIS3_GAS_BUDGET
+    pub fn from_env() -> Result<ConfigValues, anyhow::Error> {
+        let mut values = ConfigValues::default();
+        for key in CONFIG_KEYS {
+            let name = format!("SUIS3_{}", key.to_uppercase());
+            if let Ok(value) = std::env::var(&name) {
+                values.set(key, Some(&value)).map_err(|e| anyhow!("{}: {}", name, e))?;
+            }
+        }
+        Ok(values)
+    }
+
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let path = Self::path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<String> {
+        match key {
+            "network" => self.network.clone(),
+            "rpc_url" => self.rpc_url.clone(),
+            "package_id" => self.package_id.clone(),
+            "buckets_root" => self.buckets_root.clone(),
+            "gas_budget" => self.gas_budget.map(|v| v.to_string()),
+            "walrus_bin" => self.walrus_bin.clone(),
+            "walrus_timeout" => self.walrus_timeout.map(|v| v.to_string()),
+            "aggregator_url" => self.aggregator_url.clone(),
+            "download_dir" => self.download_dir.clone(),
+            "confirm_threshold" => self.confirm_threshold.map(|v| v.to_string()),
+            _ => None,
+        }
+    }
+
+    // validate and store the value, None removes the key
+    pub fn set(&mut self, key: &str, value: Option<&str>) -> Result<(), anyhow::Error> {
+        let value = value.map(|v| v.trim().to_owned());
+        match key {
+            "network" => {
+                if let Some(v) = &value {
+                    if !NETWORKS.contains(&v.as_str()) {
+                        bail!("Unknown network {}, expected one of {}", v, NETWORKS.join(", "));
+                    }
+                }
+                self.network = value;
+            }
+            "rpc_url" => {
+                if let Some(v) = &value {
+                    url::Url::parse(v).map_err(|e| anyhow!("Invalid rpc url {}: {}", v, e))?;
+                }
+                self.rpc_url = value;
+            }
+            "aggregator_url" => {
+                if let Some(v) = &value {
+                    url::Url::parse(v).map_err(|e| anyhow!("Invalid aggregator url {}: {}", v, e))?;
+                }
+                self.aggregator_url = value;
+            }
+            "package_id" | "buckets_root" => {
+                if let Some(v) = &value {
+                    ObjectID::from_hex_literal(v).map_err(|e| anyhow!("Invalid object id {}: {}", v, e))?;
+                }
+                if key == "package_id" {
+                    self.package_id = value;
+                } else {
+                    self.buckets_root = value;
+                }
+            }
+            "gas_budget" => {
+                self.gas_budget = match &value {
+                    None => None,
+                    Some(v) => match v.parse::<u64>() {
+                        Ok(budget) if budget > 0 => Some(budget),
+                        _ => bail!("Invalid gas budget {}, expected a positive amount of MIST", v),
+                    },
+                };
+            }
+            "walrus_bin" => {
+                if let Some(v) = &value {
+                    if v.len() == 0 {
+                        bail!("The walrus binary path cannot be empty");
+                    }
+                }
+                self.walrus_bin = value;
+            }
+            "download_dir" => {
+                if let Some(v) = &value {
+                    if v.len() == 0 {
+                        bail!("The download directory cannot be empty");
+                    }
+                }
+                self.download_dir = value;
+            }
+            "walrus_timeout" => {
+                self.walrus_timeout = match &value {
+                    None => None,
+                    Some(v) => match v.parse::<u64>() {
+                        Ok(seconds) if seconds > 0 => Some(seconds),
+                        _ => bail!("Invalid walrus timeout {}, expected a positive number of seconds", v),
+                    },
+                };
+            }
+            "confirm_threshold" => {
+                self.confirm_threshold = match &value {
+                    None => None,
+                    Some(v) => match v.parse::<u64>() {
+                        Ok(count) => Some(count),
+                        _ => bail!("Invalid confirm threshold {}, expected a number of objects", v),
+                    },
+                };
+            }
+            _ => bail!("Unknown config key {}, expected one of {}", key, CONFIG_KEYS.join(", ")),
+        }
+        Ok(())
+    }
+}
+
+// the effective configuration, merged with precedence flag > environment > config file > default
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub network: String,
+    pub rpc_url: String,
+    pub package_id: String,
+    pub buckets_root: String,
+    pub gas_budget: u64,
+    pub walrus_bin: String,
+    // seconds a walrus subprocess may run before it is stopped
+    pub walrus_timeout: u64,
+    // public walrus aggregator of the links printed by `share`
+    pub aggregator_url: String,
+    // folder of get without a destination
+    pub download_dir: String,
+    // bulk deletes of more objects than this ask for a confirmation
+    pub confirm_threshold: u64,
+    // (key, value, source) of each value, listed by `config list`
+    pub origins: Vec<(&'static str, String, &'static str)>,
+}
+
+impl Config {
+    pub fn resolve(flags: &ConfigValues, env: &ConfigValues, file: &ConfigValues) -> Config {
+        let layers = [(flags, "flag"), (env, "environment"), (file, "config file")];
+        let lookup = |key: &str| {
+            layers
+                .iter()
+                .find_map(|(layer, source)| layer.get(key).map(|v| (v, *source)))
+        };
+
+        let network = lookup("network").unwrap_or((DEFAULT_NETWORK.to_owned(), "default"));
+        let rpc_url = lookup("rpc_url").unwrap_or((default_rpc_url(&network.0).to_owned(), "default"));
+        let package_id = lookup("package_id").unwrap_or((DEFAULT_PACKAGE_ID.to_owned(), "default"));
+        let buckets_root = lookup("buckets_root").unwrap_or((DEFAULT_BUCKETS_ROOT.to_owned(), "default"));
+        let gas_budget = lookup("gas_budget").unwrap_or((DEFAULT_GAS_BUDGET.to_string(), "default"));
+        let walrus_bin = lookup("walrus_bin").unwrap_or((DEFAULT_WALRUS_BIN.to_owned(), "default"));
+        let walrus_timeout = lookup("walrus_timeout").unwrap_or((DEFAULT_WALRUS_TIMEOUT.to_string(), "default"));
+        let aggregator_url = lookup("aggregator_url").unwrap_or((DEFAULT_AGGREGATOR_URL.to_owned(), "default"));
+        let download_dir = lookup("download_dir").unwrap_or((DEFAULT_DOWNLOAD_DIR.to_owned(), "default"));
+        let confirm_threshold = lookup("confirm_threshold").unwrap_or((DEFAULT_CONFIRM_THRESHOLD.to_string(), "default"));
+
+        Config {
+            network: network.0.clone(),
+            rpc_url: rpc_url.0.clone(),
+            package_id: package_id.0.clone(),
+            buckets_root: buckets_root.0.clone(),
+            gas_budget: gas_budget.0.parse().unwrap_or(DEFAULT_GAS_BUDGET),
+            walrus_bin: walrus_bin.0.clone(),
+            walrus_timeout: walrus_timeout.0.parse().unwrap_or(DEFAULT_WALRUS_TIMEOUT),
+            aggregator_url: aggregator_url.0.clone(),
+            download_dir: download_dir.0.clone(),
+            confirm_threshold: confirm_threshold.0.parse().unwrap_or(DEFAULT_CONFIRM_THRESHOLD),
+            origins: vec![
+                ("network", network.0, network.1),
+                ("rpc_url", rpc_url.0, rpc_url.1),
+                ("package_id", package_id.0, package_id.1),
+                ("buckets_root", buckets_root.0, buckets_root.1),
+                ("gas_budget", gas_budget.0, gas_budget.1),
+                ("walrus_bin", walrus_bin.0, walrus_bin.1),
+                ("walrus_timeout", walrus_timeout.0, walrus_timeout.1),
+                ("aggregator_url", aggregator_url.0, aggregator_url.1),
+                ("download_dir", download_dir.0, download_dir.1),
+                ("confirm_threshold", confirm_threshold.0, confirm_threshold.1),
+            ],
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::resolve(&ConfigValues::default(), &ConfigValues::default(), &ConfigValues::default())
+    }
+}
+
+// runtime options shared by the command handlers and the operations
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    // submit write transactions without waiting for local execution
+    pub no_wait: bool,
+    // running the interactive shell, prompts can be answered on the terminal
+    pub interactive: bool,
+    // print only the errors and the requested data
+    pub quiet: bool,
+    // answer yes to every confirmation prompt
+    pub yes: bool,
+    // the shell line writes to the command after `|`, not the terminal
+    pub piped: bool,
+    // skip the rpc and walrus check before the multi-step commands
+    pub no_preflight: bool,
+    // also print the details of the transactions
+    pub verbose: bool,
+    // indent the --json records instead of one line each
+    pub json_pretty: bool,
+    // neither read nor write the completion cache
+    pub no_cache: bool,
+    // the walrus aggregator get and cat read blobs from before trying the walrus cli
+    pub read_aggregator: Option<String>,
+    // config values given on the command line, they win over the environment
+    pub flags: ConfigValues,
+    // wallet profile under ~/.suis3/profiles, the sui config dir is used without one
+    pub profile: Option<String>,
+    // wallet directory given to the library client, wins over the profile
+    pub wallet_dir: Option<PathBuf>,
+    // never create or change the wallet files, a missing piece fails the command
+    pub read_only_wallet: bool,
+    // print the gas used and the balance left after each write
+    pub show_balance: bool,
+    // the gas budget of a write is its dry run cost plus budget_margin percent
+    pub budget_from_dryrun: bool,
+    pub budget_margin: u64,
+    // coin paying the gas of the transactions instead of the first one holding enough
+    pub gas_coin: Option<ObjectID>,
+    // address signing the transactions instead of the first one of the keystore, not saved in client.yaml
+    pub active_address: Option<SuiAddress>,
+    // config values given as SUIS3_* variables, they win over the config file
+    pub env: ConfigValues,
+    pub config: Config,
+}
+
+static SETTINGS: OnceLock<RwLock<Settings>> = OnceLock::new();
+
+fn cell() -> &'static RwLock<Settings> {
+    SETTINGS.get_or_init(|| RwLock::new(Settings::default()))
+}
+
+pub fn set(settings: Settings) {
+    *cell().write().unwrap() = settings;
+}
+
+// the settings of the library client running the operation, the process wide ones otherwise
+pub fn get() -> Settings {
+    if let Some(context) = crate::client::context() {
+        return context.settings.clone();
+    }
+    cell().read().unwrap().clone()
+}
+
+// a shell line may carry its own -y
+pub fn set_yes(yes: bool) {
+    cell().write().unwrap().yes = yes;
+}
+
+// a shell line may pipe its output
+pub fn set_piped(piped: bool) {
+    cell().write().unwrap().piped = piped;
+}
+
+// re-resolve the effective configuration after the config file changed
+pub fn reload(file: &ConfigValues) {
+    let mut settings = cell().write().unwrap();
+    settings.config = Config::resolve(&settings.flags, &settings.env, file);
+}
@@ -1,3 +1,9 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
 use sui_json_rpc_types::Coin;
 use sui_keys::keystore::{AccountKeystore, FileBasedKeystore};
 use sui_sdk::{
@@ -5,16 +11,284 @@ use sui_sdk::{
     wallet_context::WalletContext,
 };
 use sui_sdk::types::{
-    base_types::SuiAddress,
+    base_types::{ObjectID, SuiAddress},
     crypto::SignatureScheme::ED25519,
+    digests::TransactionDigest,
 };
 use sui_sdk::{SuiClient, SuiClientBuilder};
 use sui_config::{
     sui_config_dir, Config, PersistedConfig, SUI_CLIENT_CONFIG, SUI_KEYSTORE_FILENAME,
 };
-use anyhow::bail;
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
 use futures::{future, stream::StreamExt};
 use log::info;
+use regex::Regex;
+
+use crate::operations::SuiS3Error;
+use crate::settings;
+use crate::walrus;
+
+pub fn explorer_tx_url(digest: &TransactionDigest) -> String {
+    format!("https://suiscan.xyz/{}/tx/{}", settings::get().config.network, digest)
+}
+
+pub const COMPRESSIONS: [&str; 2] = ["gzip", "zstd"];
+
+pub fn compress_file(src: &Path, dest: &Path, compression: &str) -> Result<(), anyhow::Error> {
+    let mut reader = fs::File::open(src)?;
+    let writer = fs::File::create(dest)?;
+    match compression {
+        "gzip" => {
+            let mut encoder = flate2::write::GzEncoder::new(writer, flate2::Compression::default());
+            std::io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?;
+        }
+        "zstd" => zstd::stream::copy_encode(reader, writer, 0)?,
+        _ => bail!("Unknown compression {}, expected one of {}", compression, COMPRESSIONS.join(", ")),
+    }
+    Ok(())
+}
+
+pub fn decompress_file(src: &Path, dest: &Path, compression: &str) -> Result<(), anyhow::Error> {
+    let reader = fs::File::open(src)?;
+    let mut writer = fs::File::create(dest)?;
+    match compression {
+        "gzip" => {
+            std::io::copy(&mut flate2::read::GzDecoder::new(reader), &mut writer)?;
+        }
+        "zstd" => zstd::stream::copy_decode(reader, writer)?,
+        _ => bail!("Unknown compression {}, expected one of {}", compression, COMPRESSIONS.join(", ")),
+    }
+    Ok(())
+}
+
+// stream the url into the file, failing once it is larger than max_size bytes
+pub async fn download_url(url: &str, dest: &Path, max_size: u64) -> Result<u64, anyhow::Error> {
+    let too_large = || anyhow!("{} is larger than {} bytes, download it and put the file instead", url, max_size);
+    let mut response = reqwest::get(url).await?.error_for_status()?;
+    if response.content_length().is_some_and(|len| len > max_size) {
+        return Err(too_large());
+    }
+    let mut file = fs::File::create(dest)?;
+    let mut size = 0u64;
+    while let Some(chunk) = response.chunk().await? {
+        size += chunk.len() as u64;
+        if size > max_size {
+            return Err(too_large());
+        }
+        file.write_all(&chunk)?;
+    }
+    Ok(size)
+}
+
+// a shell line cut at the first `|` outside quotes, the rest is the shell command of the pipe
+pub fn split_pipe(line: &str) -> (&str, Option<&str>) {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            // nothing is escaped between single quotes
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '|') => return (&line[..i], Some(&line[i + 1..])),
+            _ => {}
+        }
+    }
+    (line, None)
+}
+
+// the words of a shell line, quoted with ' or " and escaped with \ like sh, none when a quote is left open
+pub fn shell_words(line: &str) -> Option<Vec<String>> {
+    shlex::split(line)
+}
+
+// an object key against a pattern of tag add, * matches any run of characters, / included, and ? one character
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t) = (pattern.chars().collect::<Vec<_>>(), text.chars().collect::<Vec<_>>());
+    let (mut pi, mut ti) = (0, 0);
+    // the last * and where its match started, to give it one more character on a mismatch
+    let mut star = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            star = Some((sp, st + 1));
+            pi = sp + 1;
+            ti = st + 1;
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+pub fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+// guess the mime type from the file extension, for the content-type tag of put
+pub fn content_type_of(file: &str) -> &'static str {
+    let ext = Path::new(file).extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    match ext.as_str() {
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "toml" => "application/toml",
+        "yaml" | "yml" => "application/yaml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "wasm" => "application/wasm",
+        _ => "application/octet-stream",
+    }
+}
+
+// text/* and the structured text formats are safe to print on a terminal
+pub fn is_text_content_type(content_type: &str) -> bool {
+    let mime = content_type.split(';').next().unwrap_or("").trim();
+    mime.starts_with("text/")
+        || matches!(mime, "application/json" | "application/xml" | "application/toml" | "application/yaml" | "image/svg+xml")
+}
+
+// a timestamp of the contract in milliseconds, shown in local time; a value past the dates chrono can hold is not a time
+pub fn display_time(ts: u64) -> String {
+    let local = i64::try_from(ts / 1000)
+        .ok()
+        .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+        .map(|t| t.naive_utc())
+        .and_then(|t| Local.from_local_datetime(&t).earliest());
+    match local {
+        Some(date_time) => date_time.to_string(),
+        None => "(invalid timestamp)".to_owned(),
+    }
+}
+
+// s3:// is accepted as well, it is the scheme of the help text and of aws s3 muscle memory
+// the contract takes any string as an object name, quote the ones with spaces in the shell
+// the whole argument is the uri, a scheme or bucket name with other characters does not match
+static SUIS3_REGEXP: &str = r#"^(?:[sS][uU][iI][sS]3|[sS]3):\/\/(?P<bucket>[A-Za-z0-9\-\._]+)(?P<object>(?:\/.*)?)$"#;
+
+// bucket names are case-insensitive like the scheme, they are always stored and looked up in lower case,
+// object keys are kept as typed
+pub struct SuiS3Uri {
+    pub bucket: String,
+    pub object: String,
+}
+
+pub fn parse_uri(uri: &str) -> Option<SuiS3Uri> {
+    let re = Regex::new(SUIS3_REGEXP).unwrap();
+    let caps = re.captures(uri)?;
+    Some(SuiS3Uri {
+        bucket: caps["bucket"].to_lowercase(),
+        object: caps["object"].to_owned(),
+    })
+}
+
+// the characters a bucket name may hold in a suis3:// uri
+pub fn is_valid_bucket_name(name: &str) -> bool {
+    name.len() > 0 && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_'))
+}
+
+// a valid name close to the typed one: lower case, any other character as '-', without repeated or outer '-'
+pub fn sanitize_bucket_name(name: &str) -> Option<String> {
+    let mut sanitized = String::new();
+    for c in name.trim().to_lowercase().chars() {
+        let c = if c.is_ascii_alphanumeric() || matches!(c, '.' | '_') { c } else { '-' };
+        if !(c == '-' && (sanitized.is_empty() || sanitized.ends_with('-'))) {
+            sanitized.push(c);
+        }
+    }
+    let sanitized = sanitized.trim_end_matches('-');
+    (sanitized.len() > 0).then(|| sanitized.to_owned())
+}
+
+// milliseconds since the epoch of an rfc3339 time, a date, or a time ago as 30s, 15m, 12h, 7d, 2w
+pub fn parse_time(value: &str) -> Result<u64, String> {
+    let before_epoch = || format!("{} is before 1970", value);
+    if let Ok(t) = DateTime::parse_from_rfc3339(value) {
+        return u64::try_from(t.timestamp_millis()).map_err(|_| before_epoch());
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let t = Local.from_local_datetime(&d.and_hms_opt(0, 0, 0).unwrap()).earliest().ok_or(format!("{} has no midnight here", value))?;
+        return u64::try_from(t.timestamp_millis()).map_err(|_| before_epoch());
+    }
+    let (number, unit) = value.split_at(value.len() - value.chars().last().map_or(0, |c| c.len_utf8()));
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return Err(format!("{} is not an rfc3339 time, a date or a time ago like 7d", value)),
+    };
+    let ago = number
+        .parse::<u64>()
+        .map_err(|_| format!("{} is not an rfc3339 time, a date or a time ago like 7d", value))?;
+    let ago = ago.checked_mul(seconds * 1000).ok_or(format!("{} is too long ago", value))?;
+    Ok((Utc::now().timestamp_millis() as u64).saturating_sub(ago))
+}
+
+// building a client costs a round trip to the node, keep the last one per rpc url
+static SUI_CLIENT: Mutex<Option<(String, SuiClient)>> = Mutex::new(None);
+static KEYSTORE: Mutex<Option<(PathBuf, Arc<FileBasedKeystore>)>> = Mutex::new(None);
+
+pub async fn sui_client() -> Result<SuiClient, anyhow::Error> {
+    if let Some(sui) = crate::client::context().and_then(|context| context.sui.clone()) {
+        return Ok(sui);
+    }
+    let rpc_url = settings::get().config.rpc_url;
+    let cached = SUI_CLIENT.lock().unwrap().clone();
+    if let Some((url, client)) = cached {
+        if url == rpc_url {
+            return Ok(client);
+        }
+    }
+    let client = SuiClientBuilder::default()
+        .build(&rpc_url)
+        .await
+        .map_err(|e| SuiS3Error::RpcError(format!("{}: {}", rpc_url, e)))?;
+    *SUI_CLIENT.lock().unwrap() = Some((rpc_url, client.clone()));
+    Ok(client)
+}
+
+// the keystore of the wallet, read once per wallet directory
+pub fn keystore() -> Result<Arc<FileBasedKeystore>, anyhow::Error> {
+    if let Some(keystore) = crate::client::context().and_then(|context| context.keystore.clone()) {
+        return Ok(keystore);
+    }
+    let path = wallet_dir()?.join(SUI_KEYSTORE_FILENAME);
+    let mut cached = KEYSTORE.lock().unwrap();
+    if let Some((cached_path, keystore)) = cached.as_ref() {
+        if *cached_path == path {
+            return Ok(keystore.clone());
+        }
+    }
+    let keystore = Arc::new(FileBasedKeystore::new(&path)?);
+    *cached = Some((path, keystore.clone()));
+    Ok(keystore)
+}
 
 pub async fn fetch_coin(
     sui: &SuiClient,
@@ -32,48 +306,129 @@ pub async fn fetch_coin(
     Ok(coin)
 }
 
-pub async fn setup_for_write() -> Result<(SuiClient, SuiAddress, SuiAddress, Coin), anyhow::Error> {
+// the coin given with --gas-coin, a SUI coin of the sender holding at least the gas budget
+pub async fn fetch_gas_coin(sui: &SuiClient, sender: &SuiAddress, coin_id: ObjectID) -> Result<Coin, anyhow::Error> {
+    let coin_type = "0x2::sui::SUI".to_string();
+    let coin = sui
+        .coin_read_api()
+        .get_coins_stream(*sender, Some(coin_type))
+        .filter(|c| future::ready(c.coin_object_id == coin_id))
+        .boxed()
+        .next()
+        .await
+        .ok_or(anyhow!("Gas coin {} is not a SUI coin of {}", coin_id, sender))?;
+    let gas_budget = settings::get().config.gas_budget;
+    if coin.balance < gas_budget {
+        bail!("Gas coin {} holds {} MIST, less than the gas budget of {} MIST", coin_id, coin.balance, gas_budget);
+    }
+    Ok(coin)
+}
+
+pub async fn setup_for_write() -> Result<(SuiClient, SuiAddress, Coin), anyhow::Error> {
     let (client, active_address) = setup_for_read().await?;
+    if let Some(coin_id) = settings::get().gas_coin {
+        let coin = fetch_gas_coin(&client, &active_address, coin_id).await?;
+        return Ok((client, active_address, coin));
+    }
     // make sure we have some SUI (5_000_000 MIST) on this address
     let coin = fetch_coin(&client, &active_address).await?;
-    if coin.is_none() {
-        bail!("Cannot get coin with sufficient fund");
+    let Some(coin) = coin else {
+        return Err(SuiS3Error::InsufficientGas(active_address.to_string()).into());
+    };
+
+    Ok((client, active_address, coin))
+}
+
+// the address given with --assume-active-address, the active address of the wallet otherwise
+pub fn active_address(wallet: &mut WalletContext) -> Result<SuiAddress, anyhow::Error> {
+    match settings::get().active_address {
+        Some(address) => Ok(address),
+        None => wallet.active_address(),
     }
-    let wallet = retrieve_wallet()?;
-    let addresses = wallet.get_addresses();
-    let addresses = addresses
-        .into_iter()
-        .filter(|address| address != &active_address)
-        .collect::<Vec<_>>();
-    let recipient = addresses
-        .first()
-        .expect("Cannot get the recipient address needed for writing operations. Aborting");
+}
+
+pub fn parse_address(value: &str) -> Result<SuiAddress, String> {
+    value.parse::<SuiAddress>().map_err(|e| format!("{} is not a sui address: {}", value, e))
+}
 
-    Ok((client, active_address, *recipient, coin.unwrap()))
+pub fn parse_object_id(value: &str) -> Result<ObjectID, String> {
+    value.parse::<ObjectID>().map_err(|e| format!("{} is not a sui object id: {}", value, e))
 }
 
 pub async fn setup_for_read() -> Result<(SuiClient, SuiAddress), anyhow::Error> {
-    let client = SuiClientBuilder::default().build_testnet().await?;
-    // println!("Sui testnet version is: {}", client.api_version());
+    let client = sui_client().await?;
+    // println!("Sui version is: {}", client.api_version());
     let mut wallet = retrieve_wallet()?;
-    assert!(wallet.get_addresses().len() >= 2);
-    let active_address = wallet.active_address()?;
+    let active_address = active_address(&mut wallet)?;
 
     // println!("Wallet active address is: {active_address}");
     Ok((client, active_address))
 }
 
+// the rpc of the preflight check gets this long to answer
+const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// a quick look at the rpc, and at walrus when the command needs it, so an outage fails the command before any work
+pub async fn preflight(with_walrus: bool) -> Result<(), anyhow::Error> {
+    let rpc_url = settings::get().config.rpc_url;
+    let rpc = async {
+        let (client, _) = setup_for_read().await?;
+        client
+            .read_api()
+            .get_chain_identifier()
+            .await
+            .map_err(|e| anyhow!(SuiS3Error::RpcError(format!("{}: {}", rpc_url, e))))
+    };
+    tokio::time::timeout(PREFLIGHT_TIMEOUT, rpc)
+        .await
+        .map_err(|_| SuiS3Error::RpcError(format!("{}: no answer within {} seconds", rpc_url, PREFLIGHT_TIMEOUT.as_secs())))??;
+    if with_walrus {
+        tokio::task::spawn_blocking(|| walrus::backend().probe()).await??;
+    }
+    Ok(())
+}
+
+// the directory set on the library client, ~/.suis3/profiles/<name>/ with --profile, the sui config dir otherwise
+pub fn wallet_dir() -> Result<PathBuf, anyhow::Error> {
+    let settings = settings::get();
+    if let Some(dir) = settings.wallet_dir {
+        fs::create_dir_all(&dir)?;
+        return Ok(dir);
+    }
+    let Some(profile) = settings.profile else {
+        return Ok(sui_config_dir()?);
+    };
+    if profile.len() == 0 || profile.contains(['/', '\\']) || profile.starts_with('.') {
+        bail!("Invalid profile name {}", profile);
+    }
+    let home = dirs::home_dir().ok_or(anyhow!("Cannot find the home directory"))?;
+    let dir = home.join(".suis3").join("profiles").join(profile);
+    fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
+
+// the wallet of the sui config dir or the profile. a missing client config, keystore or key is created once,
+// or reported with --read-only-wallet. client.yaml is rewritten only to give it an active address it lacks
 pub fn retrieve_wallet() -> Result<WalletContext, anyhow::Error> {
-    let wallet_conf = sui_config_dir()?.join(SUI_CLIENT_CONFIG);
-    let keystore_path = sui_config_dir()?.join(SUI_KEYSTORE_FILENAME);
+    let wallet_conf = wallet_dir()?.join(SUI_CLIENT_CONFIG);
+    let keystore_path = wallet_dir()?.join(SUI_KEYSTORE_FILENAME);
+    let read_only = settings::get().read_only_wallet;
+    let missing = |what: String| anyhow!("{}, set up the wallet with the sui cli or run without --read-only-wallet to create it", what);
 
     // check if a wallet exists and if not, create a wallet and a sui client config
     if !keystore_path.exists() {
+        if read_only {
+            return Err(missing(format!("The keystore {} does not exist", keystore_path.display())));
+        }
         let keystore = FileBasedKeystore::new(&keystore_path)?;
         keystore.save()?;
+        info!("Keystore file is stored in {:?}.", &keystore_path);
     }
 
     if !wallet_conf.exists() {
+        if read_only {
+            return Err(missing(format!("The client config {} does not exist", wallet_conf.display())));
+        }
         let keystore = FileBasedKeystore::new(&keystore_path)?;
         let mut client_config = SuiClientConfig::new(keystore.into());
 
@@ -89,23 +444,35 @@ pub fn retrieve_wallet() -> Result<WalletContext, anyhow::Error> {
         info!("Client config file is stored in {:?}.", &wallet_conf);
     }
 
+    // one key is made for an empty keystore, never more
     let mut keystore = FileBasedKeystore::new(&keystore_path)?;
-    let mut client_config: SuiClientConfig = PersistedConfig::read(&wallet_conf)?;
-
-    let default_active_address = if let Some(address) = keystore.addresses().first() {
-        *address
-    } else {
-        keystore
-            .generate_and_add_new_key(ED25519, None, None, None)?
-            .0
-    };
-
-    if keystore.addresses().len() < 2 {
-        keystore.generate_and_add_new_key(ED25519, None, None, None)?;
+    if keystore.addresses().is_empty() {
+        if read_only {
+            return Err(missing(format!("The keystore {} holds no key", keystore_path.display())));
+        }
+        let address = keystore.generate_and_add_new_key(ED25519, None, None, None)?.0;
+        info!("Generated the key of {} in {:?}.", address, &keystore_path);
     }
 
-    client_config.active_address = Some(default_active_address);
-    client_config.save(&wallet_conf)?;
+    let mut client_config: SuiClientConfig = PersistedConfig::read(&wallet_conf)?;
+    let addresses = keystore.addresses();
+    match settings::get().active_address {
+        // an assumed address leaves client.yaml alone, the sui cli may be using it
+        Some(address) if !addresses.contains(&address) => {
+            bail!("{} is not an address of the keystore {}", address, keystore_path.display());
+        }
+        Some(_) => {}
+        // the active address chosen with the sui cli is kept
+        None if client_config.active_address.is_some_and(|address| addresses.contains(&address)) => {}
+        None if read_only => {
+            bail!("{} has no active address of the keystore, pick one with `sui client switch --address` or --assume-active-address",
+                  wallet_conf.display());
+        }
+        None => {
+            client_config.active_address = addresses.first().copied();
+            client_config.save(&wallet_conf)?;
+        }
+    }
 
     let wallet = WalletContext::new(&wallet_conf, Some(std::time::Duration::from_secs(60)), None)?;
 
@@ -1,11 +1,20 @@
-use std::process::Command;
-use anyhow::bail;
+use std::process::{Command, Output, Stdio};
+use anyhow::{anyhow, bail};
+use std::env;
 use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
 use serde::{Serialize, Deserialize};
 use serde_with::{DisplayFromStr, serde_as};
 
+use crate::operations::SuiS3Error;
+use crate::settings;
+
 #[serde_as]
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Clone, Debug)]
 pub struct BlobMeta {
     #[serde_as(as = "DisplayFromStr")]
     pub size: u64,
@@ -17,41 +26,382 @@ pub struct BlobMeta {
     pub walrus_epoch_till: u64,
 }
 
-pub fn walrus_blob_status(blob_id: &str) -> Result<u64, anyhow::Error> {
-    let output = Command::new("walrus")
-        .arg("blob-status")
-        .arg("--blob-id")
-        .arg(blob_id)
-        .output()?;
+// walrus versions whose `store` and `blob-status` output is parsed here, from the first up to the second
+const COMPATIBLE_WALRUS: ((u64, u64), (u64, u64)) = ((1, 0), (2, 0));
 
-    if !&output.status.success() {
-        bail!("{}", String::from_utf8_lossy(&output.stderr));
-    } else {
-        let console_output = String::from_utf8_lossy(&output.stdout);
-        let split = console_output.split("\n");        
+// the walrus probe of the preflight check gives up after this
+const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(20);
 
-        let mut end_epoch = 0;
-        for part in split {
-            if part.starts_with("End epoch:") {
-                end_epoch = part.split(":").last().unwrap().trim().parse::<u64>().unwrap();
+static DETECTED_VERSION: OnceLock<String> = OnceLock::new();
+
+// where the blobs are stored, the walrus cli unless another backend is set (e.g. a mock in tests)
+pub trait WalrusBackend: Send + Sync {
+    fn upload(&self, filename: &String) -> Result<BlobMeta, anyhow::Error>;
+    fn download(&self, blob_id: &String, dest_file: &String) -> Result<(), anyhow::Error>;
+    // the end epoch of the blob
+    fn blob_status(&self, blob_id: &str) -> Result<u64, anyhow::Error>;
+
+    // every field reported on the blob, in order, for blob-status
+    fn blob_status_detail(&self, blob_id: &str) -> Result<Vec<(String, String)>, anyhow::Error> {
+        Ok(vec![("End epoch".to_owned(), self.blob_status(blob_id)?.to_string())])
+    }
+
+    // checked before bulk operations
+    fn ensure_available(&self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    // a quick call proving walrus can be reached, made by the preflight check
+    fn probe(&self) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+
+    // the epoch walrus is in, repair compares the end epochs of the blobs with it
+    fn current_epoch(&self) -> Result<u64, anyhow::Error> {
+        bail!("this walrus backend does not tell the current epoch")
+    }
+
+    // store the file as a new blob even if walrus holds its content already, for a later end epoch
+    fn upload_again(&self, filename: &String) -> Result<BlobMeta, anyhow::Error> {
+        self.upload(filename)
+    }
+}
+
+// the walrus cli, run as a subprocess
+pub struct CliBackend;
+
+impl WalrusBackend for CliBackend {
+    fn upload(&self, filename: &String) -> Result<BlobMeta, anyhow::Error> {
+        walrus_upload_file(filename).map_err(walrus_failure)
+    }
+
+    fn upload_again(&self, filename: &String) -> Result<BlobMeta, anyhow::Error> {
+        walrus_store(filename, true).map_err(walrus_failure)
+    }
+
+    fn current_epoch(&self) -> Result<u64, anyhow::Error> {
+        walrus_current_epoch().map_err(walrus_failure)
+    }
+
+    fn download(&self, blob_id: &String, dest_file: &String) -> Result<(), anyhow::Error> {
+        walrus_download_file(blob_id, dest_file).map_err(walrus_failure)
+    }
+
+    fn blob_status(&self, blob_id: &str) -> Result<u64, anyhow::Error> {
+        walrus_blob_status(blob_id).map_err(walrus_failure)
+    }
+
+    fn blob_status_detail(&self, blob_id: &str) -> Result<Vec<(String, String)>, anyhow::Error> {
+        walrus_blob_status_detail(blob_id).map_err(walrus_failure)
+    }
+
+    fn ensure_available(&self) -> Result<(), anyhow::Error> {
+        locate_walrus_bin(&settings::get().config.walrus_bin).map_err(walrus_failure)?;
+        Ok(())
+    }
+
+    fn probe(&self) -> Result<(), anyhow::Error> {
+        walrus_info().map_err(walrus_failure)
+    }
+}
+
+// anything going wrong in or around the walrus cli, a local file error included
+fn walrus_failure(e: anyhow::Error) -> anyhow::Error {
+    SuiS3Error::WalrusFailure(e.to_string()).into()
+}
+
+static BACKEND: OnceLock<RwLock<Arc<dyn WalrusBackend>>> = OnceLock::new();
+
+fn backend_cell() -> &'static RwLock<Arc<dyn WalrusBackend>> {
+    BACKEND.get_or_init(|| RwLock::new(Arc::new(CliBackend)))
+}
+
+pub fn set_backend(backend: Arc<dyn WalrusBackend>) {
+    *backend_cell().write().unwrap() = backend;
+}
+
+// the backend of the library client running the operation, the process wide one otherwise
+pub fn backend() -> Arc<dyn WalrusBackend> {
+    if let Some(backend) = crate::client::context().and_then(|context| context.walrus_backend.clone()) {
+        return backend;
+    }
+    backend_cell().read().unwrap().clone()
+}
+
+fn walrus_command() -> Command {
+    Command::new(settings::get().config.walrus_bin)
+}
+
+// run walrus to completion like Command::output, but stop it once the timeout expires
+fn run_walrus(command: &mut Command) -> Result<Output, anyhow::Error> {
+    run_walrus_within(command, Duration::from_secs(settings::get().config.walrus_timeout))
+}
+
+fn run_walrus_within(command: &mut Command, timeout: Duration) -> Result<Output, anyhow::Error> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                anyhow!(walrus_not_found(&settings::get().config.walrus_bin))
+            } else {
+                anyhow!(e)
             }
+        })?;
+
+    // drain the pipes while waiting, a full pipe would block walrus
+    let mut stdout = child.stdout.take().unwrap();
+    let mut stderr = child.stderr.take().unwrap();
+    let stdout_reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stdout.read_to_end(&mut buf);
+        buf
+    });
+    let stderr_reader = thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stderr.read_to_end(&mut buf);
+        buf
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
         }
-        if end_epoch == 0 {
-            // incorrect end epoch
-            bail!("end epoch not found");
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            bail!("walrus did not finish within {} seconds and was stopped, see --walrus-timeout", timeout.as_secs());
         }
+        thread::sleep(Duration::from_millis(100));
+    };
+
+    Ok(Output {
+        status,
+        stdout: stdout_reader.join().unwrap_or_default(),
+        stderr: stderr_reader.join().unwrap_or_default(),
+    })
+}
+
+fn walrus_not_found(bin: &str) -> String {
+    format!("walrus binary {} is not found or not executable. Install the walrus CLI (https://docs.walrus.site/usage/setup.html), \
+             or set its path with --walrus-bin, SUIS3_WALRUS_BIN or `config set walrus_bin <path>`", bin)
+}
+
+// GET <aggregator>/v1/blobs/<blob id> streamed into the file, on a thread of its own
+// since the blocking client cannot run on the tokio runtime of the callers
+fn aggregator_download(aggregator: &str, blob_id: &str, dest_file: &str) -> Result<(), anyhow::Error> {
+    let url = format!("{}/v1/blobs/{}", aggregator.trim_end_matches('/'), blob_id);
+    let dest_file = dest_file.to_owned();
+    let timeout = Duration::from_secs(settings::get().config.walrus_timeout);
+    thread::spawn(move || -> Result<(), anyhow::Error> {
+        let client = reqwest::blocking::Client::builder().timeout(timeout).build()?;
+        let mut response = client.get(&url).send()?.error_for_status()?;
+        let mut file = fs::File::create(&dest_file)?;
+        response.copy_to(&mut file)?;
+        Ok(())
+    })
+    .join()
+    .map_err(|_| anyhow!("The download from the aggregator panicked"))?
+}
+
+// how walrus words a read of a blob it no longer stores, past its end epoch or never certified
+const BLOB_UNAVAILABLE: [&str; 4] = ["not available", "blob not found", "not certified", "expired"];
 
-        Ok(end_epoch)
+// the error of a download is walrus saying the blob is gone, rather than a network or local failure
+pub fn is_blob_unavailable(e: &anyhow::Error) -> bool {
+    let message = e.to_string().to_lowercase();
+    BLOB_UNAVAILABLE.iter().any(|words| message.contains(words))
+}
+
+// fail fast before a bulk operation rather than after partial work
+pub fn ensure_walrus_available() -> Result<(), anyhow::Error> {
+    backend().ensure_available()
+}
+
+// locate the walrus binary, either a path or a name looked up on the PATH
+pub fn locate_walrus_bin(bin: &str) -> Result<PathBuf, anyhow::Error> {
+    let path = Path::new(bin);
+    let candidates: Vec<PathBuf> = if path.components().count() > 1 {
+        vec![path.to_path_buf()]
+    } else {
+        env::var_os("PATH")
+            .map(|paths| env::split_paths(&paths).map(|dir| dir.join(bin)).collect())
+            .unwrap_or_default()
+    };
+
+    candidates.into_iter().find(|c| is_executable(c)).ok_or(anyhow!(walrus_not_found(bin)))
+}
+
+fn is_executable(path: &Path) -> bool {
+    match fs::metadata(path) {
+        Ok(m) if m.is_file() => {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                m.permissions().mode() & 0o111 != 0
+            }
+            #[cfg(not(unix))]
+            {
+                true
+            }
+        }
+        _ => false,
+    }
+}
+
+// `walrus info` reads the walrus system object on chain, cheap and without any blob
+pub fn walrus_info() -> Result<(), anyhow::Error> {
+    let output = run_walrus_within(walrus_command().arg("info"), PREFLIGHT_TIMEOUT)?;
+
+    if !&output.status.success() {
+        bail!("walrus is not reachable: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+// the "Current epoch: <n>" line of `walrus info`
+pub fn walrus_current_epoch() -> Result<u64, anyhow::Error> {
+    let output = run_walrus_within(walrus_command().arg("info"), PREFLIGHT_TIMEOUT)?;
+
+    if !&output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+    let console_output = String::from_utf8_lossy(&output.stdout);
+    let line = console_output
+        .lines()
+        .map(str::trim)
+        .find(|line| line.to_lowercase().starts_with("current epoch"))
+        .ok_or(anyhow!("current epoch not found in walrus info{}", version_note()))?;
+    let value = line.split(':').last().unwrap_or("").trim();
+    value.parse::<u64>().map_err(|_| anyhow!("invalid current epoch {}{}", value, version_note()))
+}
+
+// the version printed by `walrus --version`, e.g. "walrus 1.18.2"
+pub fn walrus_version() -> Result<String, anyhow::Error> {
+    let output = run_walrus(walrus_command().arg("--version"))?;
+
+    if !&output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+// run `walrus --version` once and keep it, warns when the version is not known to work
+pub fn probe_walrus_version() -> Result<String, anyhow::Error> {
+    if let Some(version) = DETECTED_VERSION.get() {
+        return Ok(version.clone());
+    }
+    let version = walrus_version()?;
+    let _ = DETECTED_VERSION.set(version.clone());
+    if let Err(e) = check_walrus_compatible(&version) {
+        log::warn!("{}", e);
+    }
+    Ok(version)
+}
+
+// the version found by probe_walrus_version, if it ran
+pub fn detected_walrus_version() -> Option<String> {
+    DETECTED_VERSION.get().cloned()
+}
+
+pub fn check_walrus_compatible(version: &str) -> Result<(), anyhow::Error> {
+    let Some(found) = parse_walrus_version(version) else {
+        bail!("Cannot read the walrus version from `{}`, the output of walrus may not be understood", version);
+    };
+    let (min, max) = COMPATIBLE_WALRUS;
+    if found < min || found >= max {
+        bail!(
+            "walrus {}.{} is not a version suis3 works with (>= {}.{} and < {}.{}), the output of walrus may not be understood. \
+             Install a compatible walrus CLI or set walrus_bin to one",
+            found.0, found.1, min.0, min.1, max.0, max.1
+        );
+    }
+    Ok(())
+}
+
+// (major, minor) of "walrus 1.18.2" or "walrus 1.18.2-1d9a6bc"
+fn parse_walrus_version(version: &str) -> Option<(u64, u64)> {
+    let number = version.split_whitespace().find(|w| w.starts_with(|c: char| c.is_ascii_digit()))?;
+    let mut parts = number.split(['.', '-']);
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+// appended to the errors of an output that could not be parsed
+fn version_note() -> String {
+    match detected_walrus_version() {
+        None => String::new(),
+        Some(version) => match check_walrus_compatible(&version) {
+            Err(e) => format!(". {}", e),
+            Ok(()) => format!(" in the output of {}", version),
+        },
+    }
+}
+
+// the number of an "End epoch: <n>" line
+fn parse_epoch(line: &str) -> Result<u64, anyhow::Error> {
+    let value = line.split(":").last().unwrap().trim();
+    value.parse::<u64>().map_err(|_| anyhow!("invalid end epoch {}{}", value, version_note()))
+}
+
+pub fn walrus_blob_status(blob_id: &str) -> Result<u64, anyhow::Error> {
+    let status = walrus_blob_status_detail(blob_id)?;
+    let mut end_epoch = 0;
+    if let Some((_, value)) = status.iter().find(|(key, _)| key == "End epoch") {
+        end_epoch = parse_epoch(value)?;
+    }
+    if end_epoch == 0 {
+        // incorrect end epoch
+        bail!("end epoch not found{}", version_note());
+    }
+
+    Ok(end_epoch)
+}
+
+// the "<key>: <value>" lines of `walrus blob-status`, a line without a colon is kept whole with an empty key
+pub fn walrus_blob_status_detail(blob_id: &str) -> Result<Vec<(String, String)>, anyhow::Error> {
+    let output = run_walrus(walrus_command()
+        .arg("blob-status")
+        .arg("--blob-id")
+        .arg(blob_id))?;
+
+    if !&output.status.success() {
+        bail!("{}", String::from_utf8_lossy(&output.stderr));
     }
+    let console_output = String::from_utf8_lossy(&output.stdout);
+    let status = console_output
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.len() > 0)
+        .map(|line| match line.split_once(':') {
+            Some((key, value)) => (key.trim().to_owned(), value.trim().to_owned()),
+            None => (String::new(), line.to_owned()),
+        })
+        .collect::<Vec<_>>();
+    if status.is_empty() {
+        bail!("walrus reported nothing on blob {}{}", blob_id, version_note());
+    }
+    Ok(status)
 }
 
 pub fn walrus_upload_file(filename: &String) -> Result<BlobMeta, anyhow::Error> {
+    walrus_store(filename, false)
+}
+
+// with force walrus stores a blob of its own even when it already holds the content
+fn walrus_store(filename: &String, force: bool) -> Result<BlobMeta, anyhow::Error> {
     let len = fs::metadata(filename.clone())?.len();
 
-    let output = Command::new("walrus")
-        .arg("store")
-        .arg(filename)
-        .output()?;
+    let mut command = walrus_command();
+    command.arg("store").arg(filename);
+    if force {
+        command.arg("--force");
+    }
+    let output = run_walrus(&mut command)?;
 
     if !&output.status.success() {
         bail!("{}", String::from_utf8_lossy(&output.stderr));
@@ -64,7 +414,7 @@ pub fn walrus_upload_file(filename: &String) -> Result<BlobMeta, anyhow::Error>
             if part.starts_with("Blob ID:") {
                 blob_id = part.split(":").last().unwrap().trim();
             } else if part.starts_with("End epoch:") {
-                end_epoch = part.split(":").last().unwrap().trim().parse::<u64>().unwrap();
+                end_epoch = parse_epoch(part)?;
             }
         }
 
@@ -74,7 +424,7 @@ pub fn walrus_upload_file(filename: &String) -> Result<BlobMeta, anyhow::Error>
         }
 
         if end_epoch == 0 || blob_id.len() == 0 {
-            bail!("no blob id found or incorrect end epoch");
+            bail!("no blob id found or incorrect end epoch{}", version_note());
         }
 
         let m = BlobMeta {
@@ -90,12 +440,26 @@ pub fn walrus_upload_file(filename: &String) -> Result<BlobMeta, anyhow::Error>
 }
 
 pub fn walrus_download_file(blob_id: &String, dest_file: &String) -> Result<(), anyhow::Error> {
-    let output = Command::new("walrus")
+    // walrus fails with a raw error when the folder of the destination is missing
+    if let Some(parent) = Path::new(dest_file).parent().filter(|p| p.as_os_str().len() != 0) {
+        fs::create_dir_all(parent).map_err(|e| anyhow!("Cannot create folder {}: {}", parent.display(), e))?;
+    }
+
+    if let Some(aggregator) = settings::get().read_aggregator {
+        match aggregator_download(&aggregator, blob_id, dest_file) {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                let _ = fs::remove_file(dest_file);
+                log::warn!("Cannot read blob {} from {}, reading it with the walrus cli: {}", blob_id, aggregator, e);
+            }
+        }
+    }
+
+    let output = run_walrus(walrus_command()
         .arg("read")
         .arg(blob_id)
         .arg("--out")
-        .arg(dest_file)
-        .output()?;
+        .arg(dest_file))?;
 
     if !&output.status.success() {
         bail!("{}", String::from_utf8_lossy(&output.stderr));
@@ -0,0 +1,21 @@
+// mb suggests a valid bucket name for one it would otherwise cut short
+
+use suis3::utils::{is_valid_bucket_name, sanitize_bucket_name};
+
+#[test]
+fn valid_names() {
+    assert!(is_valid_bucket_name("photos"));
+    assert!(is_valid_bucket_name("my-bucket_2.0"));
+    assert!(!is_valid_bucket_name(""));
+    assert!(!is_valid_bucket_name("my bucket"));
+    assert!(!is_valid_bucket_name("café"));
+}
+
+#[test]
+fn suggestions() {
+    assert_eq!(sanitize_bucket_name("My Bucket").as_deref(), Some("my-bucket"));
+    assert_eq!(sanitize_bucket_name("  logs / 2024  ").as_deref(), Some("logs-2024"));
+    assert_eq!(sanitize_bucket_name("a&&b").as_deref(), Some("a-b"));
+    assert_eq!(sanitize_bucket_name("-x-").as_deref(), Some("x"));
+    assert_eq!(sanitize_bucket_name("!!!"), None);
+}
@@ -0,0 +1,24 @@
+// the patterns of tag add are matched against whole object keys
+
+use suis3::utils::{glob_match, is_glob};
+
+#[test]
+fn star_and_question_mark() {
+    assert!(glob_match("/logs/*", "/logs/app.log"));
+    assert!(glob_match("/logs/*", "/logs/2024/06/app.log"));
+    assert!(glob_match("/*.log", "/logs/app.log"));
+    assert!(glob_match("/app.???", "/app.log"));
+    assert!(glob_match("/a*b*c", "/aXbYbZc"));
+    assert!(glob_match("*", "/anything"));
+    assert!(!glob_match("/logs/*", "/log/app.log"));
+    assert!(!glob_match("/app.??", "/app.log"));
+    assert!(!glob_match("/a*b*c", "/aXbYbZ"));
+}
+
+#[test]
+fn plain_keys() {
+    assert!(!is_glob("/logs/app.log"));
+    assert!(is_glob("/logs/*"));
+    assert!(glob_match("/logs/app.log", "/logs/app.log"));
+    assert!(!glob_match("/logs/app.log", "/logs/app.log.1"));
+}
@@ -0,0 +1,161 @@
+// end-to-end run of the operations against a local sui network, skipped unless SUIS3_TEST_LOCALNET is set.
+// the suis3 package has to be deployed on the localnet with the active wallet address as the owner of the
+// buckets root, given as SUIS3_PACKAGE_ID and SUIS3_BUCKETS_ROOT, and the address needs some SUI for gas.
+// walrus is replaced by a backend keeping the blobs in memory.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use suis3::operations;
+use suis3::settings::{self, Config, ConfigValues, Settings};
+use suis3::walrus::{self, BlobMeta, WalrusBackend};
+use suis3::{SuiS3Client, SuiS3ClientBuilder};
+
+#[derive(Default)]
+struct MemoryBackend {
+    blobs: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl WalrusBackend for MemoryBackend {
+    fn upload(&self, filename: &String) -> Result<BlobMeta, anyhow::Error> {
+        let content = fs::read(filename)?;
+        let blob_id = format!("{:x}", md5::compute(&content));
+        let size = content.len() as u64;
+        self.blobs.lock().unwrap().insert(blob_id.clone(), content);
+        Ok(BlobMeta {
+            size,
+            tags: Vec::new(),
+            last_write_ts: 0,
+            walrus_blob_id: blob_id,
+            walrus_epoch_till: 100,
+        })
+    }
+
+    fn download(&self, blob_id: &String, dest_file: &String) -> Result<(), anyhow::Error> {
+        let blobs = self.blobs.lock().unwrap();
+        let content = blobs.get(blob_id).ok_or(anyhow!("blob {} not found", blob_id))?;
+        fs::write(dest_file, content)?;
+        Ok(())
+    }
+
+    fn blob_status(&self, blob_id: &str) -> Result<u64, anyhow::Error> {
+        match self.blobs.lock().unwrap().contains_key(blob_id) {
+            true => Ok(100),
+            false => Err(anyhow!("blob {} not found", blob_id)),
+        }
+    }
+}
+
+fn setup() {
+    let flags = ConfigValues {
+        network: Some("localnet".to_owned()),
+        ..Default::default()
+    };
+    let env = ConfigValues::from_env().unwrap();
+    let config = Config::resolve(&flags, &env, &ConfigValues::default());
+    settings::set(Settings {
+        flags,
+        env,
+        config,
+        ..Default::default()
+    });
+    walrus::set_backend(Arc::new(MemoryBackend::default()));
+}
+
+// a client on the deployment of the test, the builder does not read the SUIS3_* variables
+fn client(backend: Arc<MemoryBackend>) -> SuiS3ClientBuilder {
+    let env = ConfigValues::from_env().unwrap();
+    let mut builder = SuiS3Client::builder().network("localnet").walrus_backend(backend);
+    if let Some(package_id) = env.package_id {
+        builder = builder.package_id(&package_id);
+    }
+    if let Some(buckets_root) = env.buckets_root {
+        builder = builder.buckets_root(&buckets_root);
+    }
+    builder
+}
+
+#[tokio::test]
+async fn bucket_and_object_lifecycle() {
+    if env::var_os("SUIS3_TEST_LOCALNET").is_none() {
+        eprintln!("SUIS3_TEST_LOCALNET is not set, skipping the localnet test");
+        return;
+    }
+
+    let dir = env::temp_dir().join(format!("suis3_localnet_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    setup();
+
+    let bucket = format!("test-{}", chrono::Utc::now().timestamp_millis());
+    let file = dir.join("hello.txt");
+    fs::write(&file, "hello walrus\n").unwrap();
+
+    operations::create_bucket(bucket.clone(), vec![]).await.unwrap();
+    assert!(operations::bucket_exists(&bucket).await.unwrap());
+
+    let (meta, _) = operations::put_object(&bucket, "/hello.txt", &file.to_str().unwrap().to_owned(), vec![])
+        .await
+        .unwrap();
+    assert_eq!(meta.size, 13);
+
+    let list = operations::get_bucket_objects(bucket.clone()).await.unwrap();
+    let uris = list.objects.iter().map(|o| o.uri.as_str()).collect::<Vec<_>>();
+    assert_eq!(uris, vec!["/hello.txt"]);
+
+    operations::tag_object(bucket.clone(), "/hello.txt".to_owned(), vec!["kind=greeting".to_owned()])
+        .await
+        .unwrap();
+    let tags = operations::list_object_tags(bucket.clone(), "/hello.txt".to_owned()).await.unwrap();
+    assert_eq!(tags, vec!["kind=greeting"]);
+
+    let stored = operations::get_object(bucket.clone(), "/hello.txt".to_owned()).await.unwrap();
+    assert_eq!(stored.walrus_blob_id, meta.walrus_blob_id);
+    let out = dir.join("hello.out").to_str().unwrap().to_owned();
+    walrus::backend().download(&stored.walrus_blob_id, &out).unwrap();
+    assert_eq!(fs::read_to_string(&out).unwrap(), "hello walrus\n");
+
+    operations::delete_object(bucket.clone(), "/hello.txt".to_owned()).await.unwrap();
+    operations::clear_object_cache();
+    assert!(operations::get_object(bucket.clone(), "/hello.txt".to_owned()).await.is_err());
+
+    operations::delete_bucket(bucket.clone()).await.unwrap();
+    assert!(!operations::bucket_exists(&bucket).await.unwrap());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn clients_do_not_share_cached_objects() {
+    if env::var_os("SUIS3_TEST_LOCALNET").is_none() {
+        eprintln!("SUIS3_TEST_LOCALNET is not set, skipping the localnet test");
+        return;
+    }
+
+    let dir = env::temp_dir().join(format!("suis3_clients_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let backend = Arc::new(MemoryBackend::default());
+    let writer = client(backend.clone()).build().await.unwrap();
+    let reader = client(backend.clone()).build().await.unwrap();
+    // the same bucket and key under a buckets root which does not exist
+    let other = client(backend).buckets_root("0x1234").build().await.unwrap();
+
+    let bucket = format!("test-{}", chrono::Utc::now().timestamp_millis());
+    let file = dir.join("hello.txt");
+    writer.create_bucket(&bucket, vec![]).await.unwrap();
+    fs::write(&file, "first\n").unwrap();
+    let (first, _) = writer.put_object(&bucket, "hello.txt", file.to_str().unwrap(), vec![]).await.unwrap();
+    assert_eq!(reader.get_object(&bucket, "hello.txt").await.unwrap().walrus_blob_id, first.walrus_blob_id);
+    assert!(other.get_object(&bucket, "hello.txt").await.is_err());
+
+    fs::write(&file, "second\n").unwrap();
+    let (second, _) = writer.put_object(&bucket, "hello.txt", file.to_str().unwrap(), vec![]).await.unwrap();
+    assert_ne!(second.walrus_blob_id, first.walrus_blob_id);
+    assert_eq!(reader.get_object(&bucket, "hello.txt").await.unwrap().walrus_blob_id, second.walrus_blob_id);
+
+    writer.delete_object(&bucket, "hello.txt").await.unwrap();
+    writer.delete_bucket(&bucket).await.unwrap();
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,65 @@
+// the --porcelain layout is a contract with scripts, these pin it byte for byte
+
+use suis3::operations::{BucketInfo, BucketObjectsInfo};
+use suis3::porcelain;
+
+fn object() -> BucketObjectsInfo {
+    BucketObjectsInfo {
+        uri: "/logs/app.log".to_owned(),
+        size: 1024,
+        tags: vec!["content-type=text/plain".to_owned(), "env=prod".to_owned()],
+        last_write_ts: 1718000000123,
+        walrus_blob_id: "M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk".to_owned(),
+        walrus_epoch_till: 42,
+    }
+}
+
+#[test]
+fn bucket_records() {
+    let info = BucketInfo {
+        name: "photos".to_owned(),
+        create_ts: 1717000000000,
+    };
+    assert_eq!(porcelain::bucket(&info, None), "photos\t1717000000000\n");
+    assert_eq!(
+        porcelain::bucket(&info, Some(&["env=prod".to_owned(), "team=a".to_owned()])),
+        "photos\t1717000000000\tenv=prod team=a\n"
+    );
+    assert_eq!(porcelain::bucket(&info, Some(&[])), "photos\t1717000000000\t\n");
+}
+
+#[test]
+fn bucket_stats_records() {
+    let info = BucketInfo {
+        name: "photos".to_owned(),
+        create_ts: 1717000000000,
+    };
+    assert_eq!(
+        porcelain::bucket_stats(&info, &["env=prod".to_owned()], Some((3, 4096))),
+        "photos\t1717000000000\tenv=prod\t3\t4096\n"
+    );
+    assert_eq!(porcelain::bucket_stats(&info, &[], None), "photos\t1717000000000\t\t\t\n");
+}
+
+#[test]
+fn object_records() {
+    assert_eq!(porcelain::object(&object(), false), "/logs/app.log\t1718000000123\n");
+    assert_eq!(
+        porcelain::object(&object(), true),
+        "/logs/app.log\t1718000000123\t1024\ttext/plain\tM4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk\t42\n"
+    );
+
+    let mut untyped = object();
+    untyped.tags.clear();
+    assert_eq!(
+        porcelain::object(&untyped, true),
+        "/logs/app.log\t1718000000123\t1024\t\tM4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk\t42\n"
+    );
+}
+
+#[test]
+fn fields_are_escaped() {
+    let mut odd = object();
+    odd.uri = "/a\tb\nc\\d".to_owned();
+    assert_eq!(porcelain::object(&odd, false), "/a\\tb\\nc\\\\d\t1718000000123\n");
+}
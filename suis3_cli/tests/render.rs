@@ -0,0 +1,27 @@
+// cat --pretty without colors: json is indented by its type, name or content, the rest is left raw
+
+use suis3::render::pretty;
+
+const JSON: &[u8] = br#"{"name":"a","tags":[1,2]}"#;
+const INDENTED: &str = "{\n  \"name\": \"a\",\n  \"tags\": [\n    1,\n    2\n  ]\n}";
+
+#[test]
+fn json_is_indented() {
+    assert_eq!(pretty(JSON, Some("application/json"), "/data", false).as_deref(), Some(INDENTED));
+    assert_eq!(pretty(JSON, None, "/data.json", false).as_deref(), Some(INDENTED));
+    assert_eq!(pretty(JSON, Some("application/octet-stream"), "/data", false).as_deref(), Some(INDENTED));
+}
+
+#[test]
+fn keys_keep_their_order() {
+    assert_eq!(pretty(br#"{"b":1,"a":2}"#, None, "/data", false).as_deref(), Some("{\n  \"b\": 1,\n  \"a\": 2\n}"));
+}
+
+#[test]
+fn raw_fallback() {
+    assert_eq!(pretty(b"{not json", Some("application/json"), "/data.json", false), None);
+    assert_eq!(pretty(b"# title", Some("text/markdown"), "/readme.md", false), None);
+    assert_eq!(pretty(JSON, Some("text/csv"), "/data", false), None);
+    assert_eq!(pretty(b"plain words", None, "/notes", false), None);
+    assert_eq!(pretty(&[0xff, 0xfe], None, "/data.json", false), None);
+}
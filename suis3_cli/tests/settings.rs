@@ -0,0 +1,109 @@
+// the effective config takes each key from the flag, else SUIS3_<KEY>, else the config file, else the default
+
+use suis3::settings::{Config, ConfigValues, CONFIG_KEYS};
+
+// a valid value of the key, different for each layer
+fn sample(key: &str, layer: u64) -> String {
+    match key {
+        "network" => ["mainnet", "devnet", "localnet"][layer as usize].to_owned(),
+        "rpc_url" | "aggregator_url" => format!("https://host{}.example.com", layer),
+        "package_id" | "buckets_root" => format!("0x{}", layer + 1),
+        "walrus_bin" | "download_dir" => format!("/opt/layer{}", layer),
+        _ => (layer + 1).to_string(),
+    }
+}
+
+fn layer(index: u64, keys: &[&str]) -> ConfigValues {
+    let mut values = ConfigValues::default();
+    for key in keys {
+        values.set(key, Some(&sample(key, index))).unwrap();
+    }
+    values
+}
+
+fn origin(config: &Config, key: &str) -> (String, &'static str) {
+    let (_, value, source) = config.origins.iter().find(|(k, _, _)| *k == key).unwrap();
+    (value.clone(), *source)
+}
+
+#[test]
+fn flag_wins_over_env_and_file() {
+    for key in CONFIG_KEYS {
+        let config = Config::resolve(&layer(0, &[key]), &layer(1, &[key]), &layer(2, &[key]));
+        assert_eq!(origin(&config, key), (sample(key, 0), "flag"), "{}", key);
+    }
+}
+
+#[test]
+fn env_wins_over_file() {
+    for key in CONFIG_KEYS {
+        let config = Config::resolve(&ConfigValues::default(), &layer(1, &[key]), &layer(2, &[key]));
+        assert_eq!(origin(&config, key), (sample(key, 1), "environment"), "{}", key);
+    }
+}
+
+#[test]
+fn file_wins_over_default() {
+    for key in CONFIG_KEYS {
+        let config = Config::resolve(&ConfigValues::default(), &ConfigValues::default(), &layer(2, &[key]));
+        assert_eq!(origin(&config, key), (sample(key, 2), "config file"), "{}", key);
+    }
+}
+
+#[test]
+fn defaults() {
+    let config = Config::default();
+    for key in CONFIG_KEYS {
+        assert_eq!(origin(&config, key).1, "default", "{}", key);
+    }
+    assert_eq!(config.network, "testnet");
+    assert_eq!(config.rpc_url, "https://fullnode.testnet.sui.io:443");
+    assert_eq!(config.gas_budget, 10_000_000);
+    assert_eq!(config.confirm_threshold, 10);
+}
+
+#[test]
+fn effective_values() {
+    let config = Config::resolve(&layer(0, &["gas_budget"]), &layer(1, &["network"]), &layer(2, &["walrus_timeout"]));
+    assert_eq!(config.gas_budget, 1);
+    assert_eq!(config.network, "devnet");
+    assert_eq!(config.walrus_timeout, 3);
+}
+
+#[test]
+fn rpc_url_follows_the_network() {
+    let config = Config::resolve(&ConfigValues::default(), &layer(1, &["network"]), &ConfigValues::default());
+    assert_eq!(origin(&config, "rpc_url"), ("https://fullnode.devnet.sui.io:443".to_owned(), "default"));
+}
+
+#[test]
+fn invalid_values_are_rejected() {
+    let mut values = ConfigValues::default();
+    assert!(values.set("gas_budget", Some("0")).is_err());
+    assert!(values.set("gas_budget", Some("lots")).is_err());
+    assert!(values.set("walrus_timeout", Some("0")).is_err());
+    assert!(values.set("network", Some("moonnet")).is_err());
+    assert!(values.set("rpc_url", Some("not a url")).is_err());
+    assert!(values.set("package_id", Some("0xzz")).is_err());
+    assert!(values.set("walrus_bin", Some("")).is_err());
+    assert!(values.set("confirm_threshold", Some("-1")).is_err());
+    assert!(values.set("color", Some("red")).is_err());
+    assert!(values.set("confirm_threshold", Some("0")).is_ok());
+}
+
+// the only test reading SUIS3_* variables, the variables of a process are shared by its test threads
+#[test]
+fn env_variables() {
+    std::env::set_var("SUIS3_NETWORK", "devnet");
+    std::env::set_var("SUIS3_GAS_BUDGET", "20000000");
+    let env = ConfigValues::from_env().unwrap();
+    assert_eq!(env.network.as_deref(), Some("devnet"));
+    assert_eq!(env.gas_budget, Some(20_000_000));
+
+    std::env::set_var("SUIS3_GAS_BUDGET", "0");
+    let err = ConfigValues::from_env().unwrap_err();
+    assert!(err.to_string().starts_with("SUIS3_GAS_BUDGET:"), "{}", err);
+
+    std::env::remove_var("SUIS3_NETWORK");
+    std::env::remove_var("SUIS3_GAS_BUDGET");
+}
@@ -0,0 +1,30 @@
+// the words of a shell line, object names with spaces have to survive the quoting
+
+use suis3::utils::{shell_words, split_pipe};
+
+fn words(line: &str) -> Vec<String> {
+    shell_words(line).unwrap()
+}
+
+#[test]
+fn quoted_arguments() {
+    assert_eq!(words("cat suis3://b/a.txt"), vec!["cat", "suis3://b/a.txt"]);
+    assert_eq!(words(r#"cat "suis3://b/my file.txt""#), vec!["cat", "suis3://b/my file.txt"]);
+    assert_eq!(words("cat 'suis3://b/it''s here'"), vec!["cat", "suis3://b/its here"]);
+    assert_eq!(words(r"get suis3://b/my\ file.txt  out"), vec!["get", "suis3://b/my file.txt", "out"]);
+    assert!(words("").is_empty());
+}
+
+#[test]
+fn open_quote() {
+    assert_eq!(shell_words(r#"cat "suis3://b/my file.txt"#), None);
+}
+
+#[test]
+fn pipe_outside_quotes() {
+    assert_eq!(split_pipe("cat suis3://b/a | grep x"), ("cat suis3://b/a ", Some(" grep x")));
+    assert_eq!(split_pipe(r#"cat "suis3://b/a|b" | wc -l"#), (r#"cat "suis3://b/a|b" "#, Some(" wc -l")));
+    assert_eq!(split_pipe("cat 'suis3://b/a|b'"), ("cat 'suis3://b/a|b'", None));
+    assert_eq!(split_pipe(r"cat suis3://b/a\|b"), (r"cat suis3://b/a\|b", None));
+    assert_eq!(split_pipe("ls"), ("ls", None));
+}
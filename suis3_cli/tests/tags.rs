@@ -0,0 +1,49 @@
+// tag add and tag del rewrite the whole tag vector of an object, the tags suis3 relies on are carried over.
+// the commands writing over, moving or deleting an object leave it alone while it is locked
+
+use suis3::operations::{add_tags, ensure_unlocked, merge_tags, protected_tags};
+
+fn tags(list: &[&str]) -> Vec<String> {
+    list.iter().map(|t| t.to_string()).collect()
+}
+
+#[test]
+fn overrides_win() {
+    let merged = merge_tags(&tags(&["team=web", "env=dev"]), &tags(&["env=prod"]));
+    assert_eq!(merged, tags(&["team=web", "env=prod"]));
+}
+
+#[test]
+fn add_keeps_the_other_tags() {
+    let current = tags(&["locked=true", "content-type=text/plain", "team=web"]);
+    let added = add_tags(&current, &tags(&["team=api", "env=prod"]));
+    assert_eq!(added, tags(&["locked=true", "content-type=text/plain", "team=api", "env=prod"]));
+}
+
+#[test]
+fn add_does_not_change_protected_tags() {
+    let current = tags(&["locked=true", "content-encoding=zstd", "latest-version=3"]);
+    let added = add_tags(&current, &tags(&["locked=false", "content-encoding=gzip", "latest-version=1"]));
+    assert_eq!(added, current);
+}
+
+#[test]
+fn protected() {
+    let current = tags(&["team=web", "locked=true", "content-type=image/png", "content-encoding=gzip", "latest-version=2", "env=prod"]);
+    assert_eq!(protected_tags(&current), tags(&["locked=true", "content-type=image/png", "content-encoding=gzip", "latest-version=2"]));
+    assert!(protected_tags(&tags(&["team=web"])).is_empty());
+}
+
+#[test]
+fn locked_objects_are_refused() {
+    let err = ensure_unlocked("docs", "/a.txt", &tags(&["team=web", "locked=true"]), false).unwrap_err();
+    assert_eq!(err.to_string(), "suis3://docs/a.txt is locked, unlock it first");
+    assert!(ensure_unlocked("docs", "/a.txt", &tags(&["locked=true"]), true).is_ok());
+}
+
+#[test]
+fn unlocked_objects_are_written() {
+    assert!(ensure_unlocked("docs", "/a.txt", &tags(&["team=web"]), false).is_ok());
+    assert!(ensure_unlocked("docs", "/a.txt", &tags(&["locked=false"]), false).is_ok());
+    assert!(ensure_unlocked("docs", "/a.txt", &[], false).is_ok());
+}
@@ -0,0 +1,54 @@
+// the suis3:// uris every command takes
+
+use suis3::utils::parse_uri;
+
+fn parts(uri: &str) -> Option<(String, String)> {
+    parse_uri(uri).map(|caps| (caps.bucket, caps.object))
+}
+
+#[test]
+fn schemes() {
+    let expected = Some(("photos".to_owned(), "/2024/a.jpg".to_owned()));
+    assert_eq!(parts("suis3://photos/2024/a.jpg"), expected);
+    assert_eq!(parts("SuiS3://photos/2024/a.jpg"), expected);
+    assert_eq!(parts("s3://photos/2024/a.jpg"), expected);
+    assert_eq!(parts("S3://photos/2024/a.jpg"), expected);
+}
+
+#[test]
+fn other_schemes_are_rejected() {
+    assert!(parse_uri("xs3://photos").is_none());
+    assert!(parse_uri("http://photos/a.jpg").is_none());
+    assert!(parse_uri("suis://photos").is_none());
+    assert!(parse_uri("photos/a.jpg").is_none());
+    assert!(parse_uri(" s3://photos").is_none());
+}
+
+#[test]
+fn bucket_only() {
+    assert_eq!(parts("suis3://photos"), Some(("photos".to_owned(), "".to_owned())));
+    assert_eq!(parts("suis3://photos/"), Some(("photos".to_owned(), "/".to_owned())));
+}
+
+#[test]
+fn bucket_with_other_characters_is_rejected() {
+    assert!(parse_uri("suis3://my bucket").is_none());
+    assert!(parse_uri("suis3://my bucket/a.txt").is_none());
+    assert!(parse_uri("suis3://café/a.txt").is_none());
+}
+
+#[test]
+fn object_keys_keep_spaces() {
+    assert_eq!(parts("suis3://docs/my notes.txt"), Some(("docs".to_owned(), "/my notes.txt".to_owned())));
+}
+
+#[test]
+fn bucket_names_are_lower_case() {
+    assert_eq!(parts("suis3://MyBucket/a.txt"), Some(("mybucket".to_owned(), "/a.txt".to_owned())));
+    assert_eq!(parts("SUIS3://PHOTOS"), Some(("photos".to_owned(), "".to_owned())));
+}
+
+#[test]
+fn object_keys_keep_their_case() {
+    assert_eq!(parts("suis3://MyBucket/Docs/README.md"), Some(("mybucket".to_owned(), "/Docs/README.md".to_owned())));
+}